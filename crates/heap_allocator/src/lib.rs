@@ -0,0 +1,218 @@
+#![feature(allocator, const_fn)]
+#![allocator]
+#![no_std]
+
+extern crate spin;
+
+use core::cmp::{max, min};
+use core::ptr;
+
+use spin::Mutex;
+
+// Every allocated block must be able to hold a Hole once it is freed
+const HOLE_SIZE: usize = 16;
+
+static HEAP: Mutex<Heap> = Mutex::new(Heap::new());
+
+// Size and alignment of the last request which could not be satisfied
+static LAST_FAILURE: Mutex<(usize, usize)> = Mutex::new((0, 0));
+
+struct Hole {
+    size: usize,
+    next: *mut Hole,
+}
+
+// Free regions of the heap kept sorted by address
+struct HoleList {
+    head: Hole,
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+fn block_size(size: usize) -> usize {
+    align_up(max(size, HOLE_SIZE), HOLE_SIZE)
+}
+
+impl HoleList {
+    const fn empty() -> HoleList {
+        HoleList {
+            head: Hole {
+                size: 0,
+                next: ptr::null_mut(),
+            },
+        }
+    }
+
+    fn allocate(&mut self, size: usize, align: usize) -> Option<usize> {
+        let align = max(align, HOLE_SIZE);
+
+        unsafe {
+            let mut prev: *mut Hole = &mut self.head;
+
+            while !(*prev).next.is_null() {
+                let hole = (*prev).next;
+                let hole_start = hole as usize;
+                let hole_end = hole_start + (*hole).size;
+
+                let alloc_start = align_up(hole_start, align);
+                let alloc_end = alloc_start + size;
+
+                if alloc_end <= hole_end {
+                    // Leftovers on both sides are multiples of HOLE_SIZE so they can
+                    // always be put back on the list
+                    let mut link = (*hole).next;
+
+                    if alloc_end < hole_end {
+                        let back = alloc_end as *mut Hole;
+                        (*back).size = hole_end - alloc_end;
+                        (*back).next = link;
+                        link = back;
+                    }
+
+                    if hole_start < alloc_start {
+                        let front = hole;
+                        (*front).size = alloc_start - hole_start;
+                        (*front).next = link;
+                        link = front;
+                    }
+
+                    (*prev).next = link;
+
+                    return Some(alloc_start);
+                }
+
+                prev = hole;
+            }
+        }
+
+        None
+    }
+
+    unsafe fn deallocate(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut Hole = &mut self.head;
+
+        while !(*prev).next.is_null() && ((*prev).next as usize) < addr {
+            prev = (*prev).next;
+        }
+
+        let hole = addr as *mut Hole;
+        (*hole).size = size;
+        (*hole).next = (*prev).next;
+
+        let next = (*hole).next;
+
+        if !next.is_null() && addr + size == next as usize {
+            (*hole).size += (*next).size;
+            (*hole).next = (*next).next;
+        }
+
+        if prev != &mut self.head as *mut Hole && prev as usize + (*prev).size == addr {
+            (*prev).size += (*hole).size;
+            (*prev).next = (*hole).next;
+        } else {
+            (*prev).next = hole;
+        }
+    }
+}
+
+struct Heap {
+    holes: HoleList,
+    // Provided by the kernel, maps at least the given number of bytes and returns
+    // the new range. Nothing can be allocated before it is set
+    grow: Option<fn(usize) -> Option<(usize, usize)>>,
+}
+
+unsafe impl Send for Heap {}
+
+impl Heap {
+    const fn new() -> Heap {
+        Heap {
+            holes: HoleList::empty(),
+            grow: None,
+        }
+    }
+
+    fn allocate(&mut self, size: usize, align: usize) -> Option<usize> {
+        let size = block_size(size);
+
+        loop {
+            if let Some(addr) = self.holes.allocate(size, align) {
+                return Some(addr);
+            }
+
+            let grow = match self.grow {
+                Some(g) => g,
+                None => return None,
+            };
+
+            // Worst case the new space is glued to a hole too small to satisfy
+            // the alignment, so ask for enough to cover it
+            match grow(size + align) {
+                Some((start, len)) => unsafe { self.holes.deallocate(start, len) },
+                None => return None,
+            }
+        }
+    }
+}
+
+pub fn init(grow: fn(usize) -> Option<(usize, usize)>) {
+    HEAP.lock().grow = Some(grow);
+}
+
+pub fn last_failure() -> (usize, usize) {
+    *LAST_FAILURE.lock()
+}
+
+#[no_mangle]
+pub extern "C" fn __rust_allocate(size: usize, align: usize) -> *mut u8 {
+    match HEAP.lock().allocate(size, align) {
+        Some(addr) => addr as *mut u8,
+        None => {
+            *LAST_FAILURE.lock() = (size, align);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn __rust_deallocate(ptr: *mut u8, old_size: usize, _align: usize) {
+    unsafe {
+        HEAP.lock().holes.deallocate(ptr as usize, block_size(old_size));
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn __rust_reallocate(ptr: *mut u8,
+                                    old_size: usize,
+                                    size: usize,
+                                    align: usize)
+                                    -> *mut u8 {
+    let new_ptr = __rust_allocate(size, align);
+
+    if !new_ptr.is_null() {
+        unsafe {
+            ptr::copy_nonoverlapping(ptr, new_ptr, min(size, old_size));
+        }
+
+        __rust_deallocate(ptr, old_size, align);
+    }
+
+    new_ptr
+}
+
+// Blocks never change size in place
+#[no_mangle]
+pub extern "C" fn __rust_reallocate_inplace(_ptr: *mut u8,
+                                            old_size: usize,
+                                            _size: usize,
+                                            _align: usize)
+                                            -> usize {
+    old_size
+}
+
+#[no_mangle]
+pub extern "C" fn __rust_usable_size(size: usize, _align: usize) -> usize {
+    size
+}
@@ -4,3 +4,5 @@ pub use self::x86_64::interrupts;
 pub use self::x86_64::cpuio;
 pub use self::x86_64::acpi;
 pub use self::x86_64::mm;
+pub use self::x86_64::task;
+pub use self::x86_64::backtrace;
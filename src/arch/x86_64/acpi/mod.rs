@@ -4,6 +4,7 @@ mod util;
 use spin::Mutex;
 
 use arch::acpi::rsdp::Rsdp;
+use cmdline;
 
 pub struct Acpi {
     rsdp: Option<&'static Rsdp>,
@@ -27,7 +28,18 @@ impl Acpi {
 
 static ACPI: Mutex<Acpi> = Mutex::new(Acpi::new());
 
+// The tables are read in place, the memory holding them can only be reclaimed
+// when ACPI is not used
+pub fn in_use() -> bool {
+    ACPI.lock().rsdp.is_some()
+}
+
 pub fn init() {
+    if cmdline::options().noacpi {
+        println!("ACPI disabled on the command line");
+        return;
+    }
+
     println!("Initializing acpi");
     ACPI.lock().init();
 }
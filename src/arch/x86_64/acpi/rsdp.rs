@@ -1,5 +1,6 @@
 use core::mem::size_of;
 use arch::acpi;
+use arch::mm::phys_to_virt;
 
 #[repr(packed, C)]
 pub struct Rsdp {
@@ -25,7 +26,7 @@ impl Rsdp {
         // TODO: Check ebda address
 
         for addr in iter {
-            let ptr = &*(addr as *const Rsdp);
+            let ptr = &*(phys_to_virt(addr) as *const Rsdp);
 
             if ptr.is_valid() {
                 return Some(ptr);
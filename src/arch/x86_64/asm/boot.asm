@@ -1,13 +1,21 @@
 global start
 global gdt64_code_offset
+global gdt64
+global gdt64_pointer
+global p4_table
+global stack_guard
+global stack_bottom
+global stack_top
 extern long_mode_start
 
-section .text
+KERNEL_BASE equ 0xffffffff80000000
+
+section .init.text
 bits 32
 start:
   cli
 
-	mov esp, stack_top
+	mov esp, stack_top - KERNEL_BASE
 	mov edi, ebx       ;Multiboot address
 
 	call test_multiboot
@@ -17,7 +25,7 @@ start:
 	call setup_page_tables
 	call enable_paging
 
-	lgdt [gdt64.pointer]
+	lgdt [gdt64_pointer_low - KERNEL_BASE]
 
 	; update selectors
 	mov ax, gdt64.data
@@ -68,20 +76,34 @@ test_long_mode:
 	jmp error
 
 setup_page_tables:
-	; map first P4 entry to P3 table
-	mov eax, p3_table
+	; map first P4 entry to P3 table, identity mapping is used only until
+	; we jump to the higher half
+	mov eax, p3_table - KERNEL_BASE
+	or eax, 0b11		; present + writable
+	mov [p4_table - KERNEL_BASE], eax
+
+	; map last P4 entry to the higher half P3 table
+	mov eax, p3_high_table - KERNEL_BASE
+	or eax, 0b11		; present + writable
+	mov [p4_table - KERNEL_BASE + 511 * 8], eax
+
+	; map the first GiB at 0xffff800000000000 as the initial physmap
+	mov eax, p3_table - KERNEL_BASE
 	or eax, 0b11		; present + writable
-	mov [p4_table], eax
+	mov [p4_table - KERNEL_BASE + 256 * 8], eax
 
 	; Recursive page table mapping
-	mov eax, p4_table
+	mov eax, p4_table - KERNEL_BASE
 	or eax, 0b11 ; present + writable
-	mov [p4_table + 511 * 8], eax
+	mov [p4_table - KERNEL_BASE + 510 * 8], eax
 
 	;map first P3 entry to P2 table
-	mov eax, p2_table
+	mov eax, p2_table - KERNEL_BASE
 	or eax, 0b11		; present + writable
-	mov [p3_table], eax
+	mov [p3_table - KERNEL_BASE], eax
+
+	; map the same P2 table at 0xffffffff80000000
+	mov [p3_high_table - KERNEL_BASE + 510 * 8], eax
 
 	; map each P2 entry to a huge 2MiB page
 	mov ecx, 0
@@ -90,7 +112,7 @@ setup_page_tables:
 	mov eax, 0x200000		; 2MiB
 	mul ecx				; start address of ecx-th page
 	or eax, 0b10000111		; preset + writable + huge
-	mov [p2_table + ecx * 8], eax 	; map ecx-th entry
+	mov [p2_table - KERNEL_BASE + ecx * 8], eax 	; map ecx-th entry
 
 	inc ecx
 	cmp ecx, 512
@@ -99,7 +121,7 @@ setup_page_tables:
 
 enable_paging:
 	; load P4 to cr3 register (cpu uses this to access the P4 table)
-	mov eax, p4_table
+	mov eax, p4_table - KERNEL_BASE
 	mov cr3, eax
 
 	; enable PAE-flag in cr4 (Physical Address Extension)
@@ -134,13 +156,20 @@ p4_table:
 	resb 4096
 p3_table:
 	resb 4096
+p3_high_table:
+	resb 4096
 p2_table:
 	resb 4096
-stack_bottom:
+; left unmapped by remap_kernel so an overflow can't run into the page tables
+stack_guard:
 	resb 4096
+; everything up to memory::init, remap_kernel and the heap runs on this stack
+stack_bottom:
+	resb 4096 * 16
 stack_top:
 
-section .rodata
+; writable as the TSS descriptor is filled in at runtime
+section .data
 bits 64
 gdt64:
 	dq 0								; zero entry
@@ -148,6 +177,14 @@ gdt64:
 	dq (1 << 44) | (1 << 47) | (1 << 41) | (1 << 43) | (1 << 53)	; code segment
 .data: equ $ - gdt64
 	dq (1 << 44) | (1 << 47) | (1 << 41) 				; data segment
-.pointer:
-	dw $ - gdt64 - 1
+.tss: equ $ - gdt64
+	dq 0								; TSS descriptor, 16 bytes
+	dq 0
+.end:
+gdt64_pointer:
+	dw gdt64.end - gdt64 - 1
 	dq gdt64
+; used to load the gdt before paging is enabled
+gdt64_pointer_low:
+	dw gdt64.end - gdt64 - 1
+	dq gdt64 - KERNEL_BASE
@@ -1,15 +1,17 @@
 global long_mode_start
+extern p4_table
+extern stack_top
+extern gdt64_pointer
 
-section .text
+PHYSMAP_BASE equ 0xffff800000000000
+
+section .init.text
 bits 64
 long_mode_start:
-	extern rust_main
 	call setup_SSE
-	call rust_main
 
-	mov rax, 0x2f592f412f4b2f4f
-	mov qword [0xb8000], rax
-	hlt
+	mov rax, higher_half_start
+	jmp rax
 
 ; Check for SSE and enable it. If it's not supported throw error "a".
 setup_SSE:
@@ -41,3 +43,29 @@ error:
 	mov byte [0xb800e], al
 	hlt
 	jmp error
+
+section .text
+bits 64
+higher_half_start:
+	mov rsp, stack_top
+
+	; gdt pointer used in protected mode holds the physical address
+	mov rax, gdt64_pointer
+	lgdt [rax]
+
+	; remove the identity mapping
+	mov rax, p4_table
+	mov qword [rax], 0
+	mov rax, cr3
+	mov cr3, rax
+
+	; null frame pointer ends backtraces
+	xor rbp, rbp
+
+	extern rust_main
+	call rust_main
+
+	mov rax, 0x2f592f412f4b2f4f
+	mov rbx, PHYSMAP_BASE + 0xb8000
+	mov qword [rbx], rax
+	hlt
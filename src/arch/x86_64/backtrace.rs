@@ -0,0 +1,151 @@
+use core::fmt;
+use core::mem::size_of;
+
+use arch::mm::VirtAddr;
+use arch::task;
+use elf;
+use memory::stack;
+
+// Stops runaway walks through a corrupted chain
+const MAX_FRAMES: usize = 64;
+
+extern "C" {
+    static stack_bottom: u8;
+    static stack_top: u8;
+}
+
+// Bounds of the stack holding the address, either the boot stack, an IST stack
+// or one from memory::stack
+fn stack_bounds(rsp: VirtAddr) -> Option<(VirtAddr, VirtAddr)> {
+    let (bottom, top) = unsafe {
+        (&stack_bottom as *const _ as VirtAddr, &stack_top as *const _ as VirtAddr)
+    };
+
+    if rsp >= bottom && rsp < top {
+        Some((bottom, top))
+    } else {
+        task::ist_stack_bounds(rsp).or_else(|| stack::bounds(rsp))
+    }
+}
+
+fn is_digit(b: u8) -> bool {
+    b >= b'0' && b <= b'9'
+}
+
+// Legacy Rust symbols are _ZN followed by length prefixed path segments and E,
+// the last segment being the hash. Anything else is printed as is
+struct Demangle(&'static str);
+
+impl fmt::Display for Demangle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.0;
+
+        if !name.starts_with("_ZN") || !name.ends_with('E') {
+            return f.write_str(name);
+        }
+
+        let mut rest = &name[3..name.len() - 1];
+        let mut segments = 0;
+
+        // Validate first so a malformed name is not printed half demangled
+        while !rest.is_empty() {
+            let digits = rest.bytes().take_while(|&b| is_digit(b)).count();
+
+            let len = match rest[..digits].parse::<usize>() {
+                Ok(len) if len <= rest.len() - digits => len,
+                _ => return f.write_str(name),
+            };
+
+            rest = &rest[digits + len..];
+            segments += 1;
+        }
+
+        let mut rest = &name[3..name.len() - 1];
+
+        for idx in 0..segments {
+            let digits = rest.bytes().take_while(|&b| is_digit(b)).count();
+            let len = rest[..digits].parse::<usize>().unwrap();
+            let segment = &rest[digits..digits + len];
+
+            rest = &rest[digits + len..];
+
+            let is_hash = idx == segments - 1 && segment.len() == 17 && segment.starts_with('h');
+
+            if is_hash {
+                break;
+            }
+
+            if idx > 0 {
+                try!(f.write_str("::"));
+            }
+
+            try!(f.write_str(segment));
+        }
+
+        Ok(())
+    }
+}
+
+fn print_frame(idx: usize, ret: VirtAddr) {
+    // The return address may already belong to the next function if the call
+    // was the last instruction
+    match elf::resolve(ret - 1) {
+        Some((name, offset)) => {
+            println!("  {:>2}: 0x{:x} {}+0x{:x}", idx, ret, Demangle(name), offset + 1)
+        }
+        None => println!("  {:>2}: 0x{:x} <unknown>", idx, ret),
+    }
+}
+
+// Follows the saved frame pointers of the current stack. Every frame must lie
+// above the previous one and inside the stack, the walk stops otherwise. Runs
+// in the panic handler, which may have interrupted the holder of any lock, so
+// the stack and symbol lookups give up instead of waiting for one
+pub fn print() {
+    let (rbp, rsp): (VirtAddr, VirtAddr);
+
+    unsafe {
+        asm!("mov $0, rbp
+              mov $1, rsp"
+             : "=r"(rbp), "=r"(rsp)
+             :
+             :
+             : "intel", "volatile");
+    }
+
+    let (_, top) = match stack_bounds(rsp) {
+        Some(b) => b,
+        None => {
+            println!("Backtrace: unknown stack at 0x{:x}", rsp);
+            return;
+        }
+    };
+
+    println!("Backtrace:");
+
+    let mut frame = rbp;
+
+    for idx in 0..MAX_FRAMES {
+        if frame < rsp || frame + 2 * size_of::<usize>() > top ||
+           frame % size_of::<usize>() != 0 {
+            break;
+        }
+
+        let (next, ret) = unsafe {
+            let ptr = frame as *const usize;
+            (*ptr, *ptr.offset(1))
+        };
+
+        if ret == 0 {
+            break;
+        }
+
+        print_frame(idx, ret);
+
+        if next <= frame {
+            break;
+        }
+
+        frame = next;
+    }
+}
@@ -78,6 +78,11 @@ impl Idt {
         e.selector = gdt_code_selector.bits();
         e.type_and_attr = flags;
     }
+
+    // Makes the CPU switch to the given interrupt stack table entry
+    pub fn set_ist(&mut self, num: usize, ist: u8) {
+        self.table[num].zero = ist & 0b111;
+    }
 }
 
 pub unsafe fn test() {
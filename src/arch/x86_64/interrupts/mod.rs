@@ -3,6 +3,11 @@ mod idt;
 
 use spin::Mutex;
 
+use arch::mm;
+use arch::task;
+use memory::stack;
+use x86;
+
 static PICS: Mutex<pic::ChainedPics> = Mutex::new(unsafe { pic::ChainedPics::new(0x20, 0x28) });
 
 static IDT: Mutex<idt::Idt> = Mutex::new(idt::Idt::new());
@@ -22,6 +27,11 @@ pub struct InterruptContext {
     _pad1: u32,
     error_code: u32,
     _pad2: u32,
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
 }
 
 #[no_mangle]
@@ -29,8 +39,40 @@ pub extern "C" fn isr_handler(ctx: &InterruptContext) {
     match ctx.int_id {
         80 => println!("INTERRUPTS WORKING {} 0x{:x}", ctx.int_id, ctx.error_code),
         33 => println!("Keyboard interrupt detected"),
+        8 => {
+            // A page fault which could not push its frame leaves the address in CR2
+            let addr = unsafe { x86::controlregs::cr2() } as usize;
+
+            if stack::is_guard_page(addr) {
+                println!("STACK OVERFLOW at 0x{:x}, rip 0x{:x}, rsp 0x{:x}",
+                         addr,
+                         ctx.rip,
+                         ctx.rsp);
+            } else {
+                println!("DOUBLE FAULT, rip 0x{:x}, rsp 0x{:x}", ctx.rip, ctx.rsp);
+            }
+            loop{};
+        },
         14 => {
-            println!("PAGE FAULT");
+            let addr = unsafe { x86::controlregs::cr2() } as usize;
+
+            if mm::fault::handle_page_fault(addr, ctx.error_code) {
+                return;
+            }
+
+            // Overflows usually escalate to a double fault, unless there was
+            // still room to push the fault frame
+            if stack::is_guard_page(addr) {
+                println!("STACK OVERFLOW at 0x{:x}, rip 0x{:x}, rsp 0x{:x}",
+                         addr,
+                         ctx.rip,
+                         ctx.rsp);
+            } else {
+                println!("PAGE FAULT at 0x{:x}, rip 0x{:x}, error 0x{:x}",
+                         addr,
+                         ctx.rip,
+                         ctx.error_code);
+            }
             loop{};
         },
         _ => {
@@ -47,7 +89,12 @@ pub extern "C" fn isr_handler(ctx: &InterruptContext) {
 pub fn init() {
     unsafe {
         PICS.lock().init();
-        IDT.lock().init();
+        {
+            let mut idt = IDT.lock();
+
+            idt.init();
+            idt.set_ist(8, task::DOUBLE_FAULT_IST);
+        }
 
         idt::test();
 
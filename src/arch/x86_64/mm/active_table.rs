@@ -0,0 +1,119 @@
+use core::ops::{Deref, DerefMut};
+
+use memory::Frame;
+use memory::stats;
+use memory::stats::Usage;
+use super::entry::*;
+use super::mapper::{Mapper, RECURSIVE_ENTRY, KERNEL_ENTRY_START};
+use super::table::{Level4, ENTRY_CNT};
+use super::temporary_page::TemporaryPage;
+use x86;
+
+fn active_p4_frame() -> Frame {
+    Frame::new(unsafe { x86::controlregs::cr3() } as usize)
+}
+
+pub struct ActivePageTable {
+    mapper: Mapper,
+}
+
+impl Deref for ActivePageTable {
+    type Target = Mapper;
+
+    fn deref(&self) -> &Mapper {
+        &self.mapper
+    }
+}
+
+impl DerefMut for ActivePageTable {
+    fn deref_mut(&mut self) -> &mut Mapper {
+        &mut self.mapper
+    }
+}
+
+impl ActivePageTable {
+    pub const fn new() -> ActivePageTable {
+        ActivePageTable { mapper: Mapper::new() }
+    }
+
+    // Runs f with the mapper operating on the inactive table. Recursive entry of the
+    // active P4 is pointed at the inactive one for the duration of the call
+    pub fn with<F>(&mut self,
+                   table: &mut InactivePageTable,
+                   temporary_page: &mut TemporaryPage,
+                   f: F)
+        where F: FnOnce(&mut Mapper)
+    {
+        {
+            let backup = active_p4_frame();
+
+            // Active P4 will not be reachable through the recursive mapping anymore
+            let p4_table = temporary_page.map_table_frame::<Level4>(Frame { number: backup.number },
+                                                                    self);
+
+            self.p4_mut()[RECURSIVE_ENTRY].set(Frame { number: table.p4_frame.number },
+                                               PRESENT | WRITABLE);
+
+            unsafe {
+                x86::tlb::flush_all();
+            }
+
+            f(&mut self.mapper);
+
+            p4_table[RECURSIVE_ENTRY].set(backup, PRESENT | WRITABLE);
+
+            unsafe {
+                x86::tlb::flush_all();
+            }
+        }
+
+        temporary_page.unmap(self);
+    }
+
+    pub fn switch(&mut self, new_table: InactivePageTable) -> InactivePageTable {
+        let old_table = InactivePageTable { p4_frame: active_p4_frame() };
+
+        unsafe {
+            x86::controlregs::cr3_write(new_table.p4_frame.address() as u64);
+        }
+
+        old_table
+    }
+}
+
+pub struct InactivePageTable {
+    p4_frame: Frame,
+}
+
+impl InactivePageTable {
+    pub fn new(frame: Frame,
+               active: &mut ActivePageTable,
+               temporary_page: &mut TemporaryPage)
+               -> InactivePageTable {
+        {
+            let table = temporary_page.map_table_frame::<Level4>(Frame { number: frame.number },
+                                                                 active);
+
+            table.clear();
+
+            // Kernel part is shared by all page tables
+            for idx in KERNEL_ENTRY_START..ENTRY_CNT {
+                if idx != RECURSIVE_ENTRY {
+                    table[idx] = active.p4()[idx];
+                }
+            }
+
+            table[RECURSIVE_ENTRY].set(Frame { number: frame.number }, PRESENT | WRITABLE);
+        }
+
+        temporary_page.unmap(active);
+
+        stats::add(Usage::PageTables, 1);
+
+        InactivePageTable { p4_frame: frame }
+    }
+
+    pub fn p4_frame(&self) -> &Frame {
+        &self.p4_frame
+    }
+}
@@ -11,10 +11,19 @@ bitflags! {
         const DIRTY         = 1 << 6,
         const HUGE_PAGE     = 1 << 7,
         const GLOBAL        = 1 << 8,
+        // Available to software: read-only page to be copied on write
+        const COW           = 1 << 9,
+        // PAT bit of huge entries, part of the address in every other entry
+        const HUGE_PAT      = 1 << 12,
         const NO_EXECUTE    = 1 << 63,
     }
 }
 
+const ADDRESS_MASK: u64 = 0x000fffff_fffff000;
+
+// Huge pages are at least 2MiB aligned, which leaves bit 12 free for the PAT
+const HUGE_ADDRESS_MASK: u64 = ADDRESS_MASK & !HUGE_PAT.bits;
+
 impl Entry {
     pub fn clear(&mut self) {
         self.bits = 0;
@@ -30,12 +39,30 @@ impl Entry {
 
     pub fn frame(&self) -> Option<Frame> {
         if self.contains(PRESENT) {
-            Some(Frame::new(self.bits as usize & 0x000fffff_fffff000))
+            Some(Frame::new((self.bits & ADDRESS_MASK) as usize))
+        } else {
+            None
+        }
+    }
+
+    pub fn flags(&self) -> Entry {
+        Entry { bits: self.bits & !ADDRESS_MASK }
+    }
+
+    // Counterparts of frame() and flags() for huge P2 and P3 entries, in P1
+    // entries bit 7 is the PAT bit and bit 12 part of the address
+    pub fn huge_frame(&self) -> Option<Frame> {
+        if self.contains(PRESENT) {
+            Some(Frame::new((self.bits & HUGE_ADDRESS_MASK) as usize))
         } else {
             None
         }
     }
 
+    pub fn huge_flags(&self) -> Entry {
+        Entry { bits: self.bits & !HUGE_ADDRESS_MASK }
+    }
+
     pub fn set(&mut self, frame: Frame, flags: Entry) {
         self.bits = frame.address() as u64;
         self.insert(flags);
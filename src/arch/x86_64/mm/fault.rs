@@ -0,0 +1,82 @@
+use core::ptr;
+
+use spin::Mutex;
+
+use memory;
+use memory::{Frame, PAGE_SIZE};
+use memory::vmalloc;
+use super::{MAPPER, VirtAddr, phys_to_virt};
+use super::entry::*;
+use super::page::Page;
+
+bitflags! {
+    flags PageFaultError: u32 {
+        const PROTECTION_VIOLATION  = 1 << 0,
+        const CAUSED_BY_WRITE       = 1 << 1,
+        const USER_MODE             = 1 << 2,
+        const MALFORMED_TABLE       = 1 << 3,
+        const INSTRUCTION_FETCH     = 1 << 4,
+    }
+}
+
+// Mapped copy-on-write wherever lazy pages are only read from, it keeps one
+// reference of its own so it is never freed
+static ZERO_FRAME: Mutex<Option<usize>> = Mutex::new(None);
+
+fn allocate_zeroed() -> Option<Frame> {
+    memory::allocate().map(|frame| {
+        unsafe {
+            ptr::write_bytes(phys_to_virt(frame.address()) as *mut u8, 0, PAGE_SIZE);
+        }
+
+        frame
+    })
+}
+
+fn map_zeroed(virt: VirtAddr) -> bool {
+    let frame = match allocate_zeroed() {
+        Some(f) => f,
+        None => return false,
+    };
+
+    MAPPER.lock().map_to(Page::new(virt), frame, WRITABLE | NO_EXECUTE);
+
+    true
+}
+
+fn map_zero_page(virt: VirtAddr) -> bool {
+    let mut zero = ZERO_FRAME.lock();
+
+    if zero.is_none() {
+        *zero = allocate_zeroed().map(|f| f.number);
+    }
+
+    match *zero {
+        Some(number) => {
+            MAPPER.lock().map_cow(Page::new(virt), &Frame { number: number }, NO_EXECUTE);
+            true
+        }
+        None => false,
+    }
+}
+
+// Returns true if the fault was resolved and the faulting instruction can be
+// restarted
+pub fn handle_page_fault(addr: VirtAddr, error_code: u32) -> bool {
+    let error = PageFaultError::from_bits_truncate(error_code);
+    let page = addr & !(PAGE_SIZE - 1);
+
+    if error.contains(PROTECTION_VIOLATION | CAUSED_BY_WRITE) {
+        return MAPPER.lock().handle_cow(Page::new(page));
+    }
+
+    if !error.contains(PROTECTION_VIOLATION) && vmalloc::is_lazy(addr) {
+        if error.contains(CAUSED_BY_WRITE) {
+            return map_zeroed(page);
+        }
+
+        return map_zero_page(page);
+    }
+
+    false
+}
@@ -0,0 +1,99 @@
+use memory;
+use memory::{Frame, PAGE_SIZE};
+use memory::vmalloc;
+use memory::vmalloc::RegionKind;
+use super::{MAPPER, LOW_MEMORY_END, PhysAddr, VirtAddr};
+use super::entry::*;
+use super::page::Page;
+use x86;
+
+// Memory types encoded in the IA32_PAT MSR
+const PAT_UC: u64 = 0x00;
+const PAT_WC: u64 = 0x01;
+const PAT_WT: u64 = 0x04;
+const PAT_WB: u64 = 0x06;
+const PAT_UC_MINUS: u64 = 0x07;
+
+// In 4KiB page table entries the PAT bit takes the place of HUGE_PAGE
+const PAT: Entry = HUGE_PAGE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheType {
+    WriteBack,
+    WriteThrough,
+    WriteCombining,
+    Uncached,
+}
+
+impl CacheType {
+    // PAT, PCD and PWT select one of the entries programmed in init()
+    fn flags(&self) -> Entry {
+        match *self {
+            CacheType::WriteBack => Entry::empty(),
+            CacheType::WriteThrough => WRT_THROUGH,
+            CacheType::WriteCombining => PAT,
+            CacheType::Uncached => NO_CACHE | WRT_THROUGH,
+        }
+    }
+}
+
+// Entries 0-3 keep their power-on values, entry 4 becomes write-combining
+pub fn init() {
+    let pat = PAT_WB | PAT_WT << 8 | PAT_UC_MINUS << 16 | PAT_UC << 24 |
+              PAT_WC << 32 | PAT_WT << 40 | PAT_UC_MINUS << 48 | PAT_UC << 56;
+
+    unsafe {
+        x86::msr::wrmsr(x86::msr::IA32_PAT, pat);
+        x86::tlb::flush_all();
+    }
+}
+
+// RAM and low memory are refused, mapping them with another cache type than
+// the physmap's write-back one is undefined behaviour
+pub fn ioremap(phys: PhysAddr, size: usize, cache: CacheType) -> Option<VirtAddr> {
+    let end = match phys.checked_add(size) {
+        Some(end) if size > 0 => end,
+        _ => return None,
+    };
+
+    if phys < LOW_MEMORY_END || memory::is_ram(phys, end) {
+        return None;
+    }
+
+    let offset = phys & (PAGE_SIZE - 1);
+    let start = phys - offset;
+    let pages = (size + offset + PAGE_SIZE - 1) / PAGE_SIZE;
+
+    let virt = match vmalloc::reserve(pages * PAGE_SIZE, RegionKind::Ioremap) {
+        Some(v) => v,
+        None => return None,
+    };
+
+    let mut mapper = MAPPER.lock();
+
+    for page in 0..pages {
+        mapper.map_to(Page::new(virt + page * PAGE_SIZE),
+                      Frame::new(start + page * PAGE_SIZE),
+                      WRITABLE | NO_EXECUTE | cache.flags());
+    }
+
+    Some(virt + offset)
+}
+
+// Device memory is not owned by the frame allocator so frames are not released
+pub fn iounmap(virt: VirtAddr) {
+    let virt = virt & !(PAGE_SIZE - 1);
+
+    let size = vmalloc::region_size(virt, RegionKind::Ioremap)
+                   .expect("iounmap of address not returned by ioremap");
+
+    {
+        let mut mapper = MAPPER.lock();
+
+        for offset in (0..size).step_by(PAGE_SIZE) {
+            mapper.unmap(Page::new(virt + offset));
+        }
+    }
+
+    vmalloc::release(virt, RegionKind::Ioremap);
+}
@@ -1,19 +1,36 @@
+use core::ptr;
 use core::ptr::Unique;
 
 use memory::Frame;
 use memory::PAGE_SIZE;
-use super::{VirtAddr, PhysAddr};
+use super::{VirtAddr, PhysAddr, phys_to_virt};
 use super::table;
 use super::table::ENTRY_CNT;
-use super::page::Page;
+use super::page::{Page, PAGE_2M_SIZE, PAGE_1G_SIZE};
 use super::entry::*;
+use memory;
+use memory::stats;
+use memory::stats::Usage;
 use x86;
 
 unsafe fn flush(addr: usize) {
     asm!("invlpg ($0)" :: "r" (addr) : "memory");
 }
 
-const P4: *mut table::Table<table::Level4> = 0xffffffff_fffff000 as *mut _;
+pub const RECURSIVE_ENTRY: usize = 510;
+// P4 entries from this one up map the kernel
+pub const KERNEL_ENTRY_START: usize = 256;
+pub const P4: *mut table::Table<table::Level4> = 0xffffff7f_bfdfe000 as *mut _;
+
+pub fn has_1g_pages() -> bool {
+    x86::cpuid::cpuid1(0x8000_0001).edx & (1 << 26) != 0
+}
+
+// Flags of a table entry pointing to the split huge page. NO_EXECUTE stays at the
+// leaf entries as it would apply to the whole table otherwise
+fn table_flags(huge: Entry) -> Entry {
+    PRESENT | WRITABLE | (huge & USER)
+}
 
 pub struct Mapper {
     p4: Unique<table::PageDirectory>,
@@ -49,22 +66,334 @@ impl Mapper {
         }
     }
 
-    pub fn unmap(&mut self, page: Page) {
-        let p1 = self.p4_mut()
-                     .next_table_mut(page.p4_index())
-                     .and_then(|p3| p3.next_table_mut(page.p3_index()))
-                     .and_then(|p2| p2.next_table_mut(page.p2_index()))
-                     .expect("Mapping code does not support huge pages");
+    pub fn map_to_2m(&mut self, page: Page, frame: Frame, flags: Entry) {
+        assert!(page.address() % PAGE_2M_SIZE == 0, "Page not 2MiB aligned");
+        assert!(frame.address() % PAGE_2M_SIZE == 0, "Frame not 2MiB aligned");
+
+        let mut p3 = self.p4_mut().next_table_create(page.p4_index());
+        let mut p2 = p3.next_table_create(page.p3_index());
+
+        assert!(p2[page.p2_index()].is_unused(), "Page already mapped");
+
+        p2[page.p2_index()].set(frame, flags | PRESENT | HUGE_PAGE);
+
+        unsafe {
+            x86::tlb::flush(page.address());
+        }
+    }
+
+    pub fn map_to_1g(&mut self, page: Page, frame: Frame, flags: Entry) {
+        assert!(has_1g_pages(), "CPU does not support 1GiB pages");
+        assert!(page.address() % PAGE_1G_SIZE == 0, "Page not 1GiB aligned");
+        assert!(frame.address() % PAGE_1G_SIZE == 0, "Frame not 1GiB aligned");
+
+        let mut p3 = self.p4_mut().next_table_create(page.p4_index());
 
-        let mut pd = p1[page.p1_index()];
+        assert!(p3[page.p3_index()].is_unused(), "Page already mapped");
 
-        pd.clear();
+        p3[page.p3_index()].set(frame, flags | PRESENT | HUGE_PAGE);
 
         unsafe {
             x86::tlb::flush(page.address());
         }
     }
 
+    pub fn unmap_2m(&mut self, page: Page) -> Option<Frame> {
+        let frame = {
+            let p2 = match self.p4_mut()
+                               .next_table_mut(page.p4_index())
+                               .and_then(|p3| p3.next_table_mut(page.p3_index())) {
+                Some(p2) => p2,
+                None => return None,
+            };
+
+            let entry = &mut p2[page.p2_index()];
+
+            assert!(entry.is_unused() || entry.contains(HUGE_PAGE),
+                    "Page not mapped as 2MiB page");
+
+            let frame = entry.huge_frame();
+            entry.clear();
+            frame
+        };
+
+        self.finish_unmap(&page, frame)
+    }
+
+    pub fn unmap_1g(&mut self, page: Page) -> Option<Frame> {
+        let frame = {
+            let p3 = match self.p4_mut().next_table_mut(page.p4_index()) {
+                Some(p3) => p3,
+                None => return None,
+            };
+
+            let entry = &mut p3[page.p3_index()];
+
+            assert!(entry.is_unused() || entry.contains(HUGE_PAGE),
+                    "Page not mapped as 1GiB page");
+
+            let frame = entry.huge_frame();
+            entry.clear();
+            frame
+        };
+
+        self.finish_unmap(&page, frame)
+    }
+
+    // Replaces huge P3 entry with a P2 table of 2MiB pages covering the same range
+    fn split_1g(p3: &mut table::Table<table::Level3>, idx: usize) {
+        let huge = p3[idx].huge_flags();
+        let start = p3[idx].huge_frame().expect("Splitting unmapped page");
+        let frame = memory::allocate().expect("Out of memory");
+
+        stats::add(Usage::PageTables, 1);
+
+        {
+            let p2 = unsafe {
+                &mut *(phys_to_virt(frame.address()) as *mut table::Table<table::Level2>)
+            };
+
+            for i in 0..ENTRY_CNT {
+                p2[i].set(Frame { number: start.number + i * ENTRY_CNT }, huge);
+            }
+        }
+
+        p3[idx].set(frame, table_flags(huge));
+
+        unsafe {
+            x86::tlb::flush_all();
+        }
+    }
+
+    // Replaces huge P2 entry with a P1 table of 4KiB pages covering the same range
+    fn split_2m(p2: &mut table::Table<table::Level2>, idx: usize) {
+        let huge = p2[idx].huge_flags();
+        let start = p2[idx].huge_frame().expect("Splitting unmapped page");
+        let frame = memory::allocate().expect("Out of memory");
+
+        stats::add(Usage::PageTables, 1);
+
+        // 4KiB entries keep the PAT bit where huge entries have HUGE_PAGE
+        let mut flags = huge - HUGE_PAGE - HUGE_PAT;
+
+        if huge.contains(HUGE_PAT) {
+            flags.insert(HUGE_PAGE);
+        }
+
+        {
+            let p1 = unsafe {
+                &mut *(phys_to_virt(frame.address()) as *mut table::Table<table::Level1>)
+            };
+
+            for i in 0..ENTRY_CNT {
+                p1[i].set(Frame { number: start.number + i }, flags);
+            }
+        }
+
+        p2[idx].set(frame, table_flags(huge));
+
+        unsafe {
+            x86::tlb::flush_all();
+        }
+    }
+
+    // Returns P1 table for the page, splitting any huge page it is part of
+    fn p1_split_mut(&mut self, page: &Page) -> Option<&mut table::Table<table::Level1>> {
+        let p3 = match self.p4_mut().next_table_mut(page.p4_index()) {
+            Some(p3) => p3,
+            None => return None,
+        };
+
+        if p3[page.p3_index()].contains(PRESENT | HUGE_PAGE) {
+            Mapper::split_1g(p3, page.p3_index());
+        }
+
+        let p2 = match p3.next_table_mut(page.p3_index()) {
+            Some(p2) => p2,
+            None => return None,
+        };
+
+        if p2[page.p2_index()].contains(PRESENT | HUGE_PAGE) {
+            Mapper::split_2m(p2, page.p2_index());
+        }
+
+        p2.next_table_mut(page.p2_index())
+    }
+
+    // P1 entry of the page, huge pages are not split
+    fn p1_entry_mut(&mut self, page: &Page) -> Option<&mut Entry> {
+        self.p4_mut()
+            .next_table_mut(page.p4_index())
+            .and_then(|p3| p3.next_table_mut(page.p3_index()))
+            .and_then(|p2| p2.next_table_mut(page.p2_index()))
+            .map(|p1| &mut p1[page.p1_index()])
+    }
+
+    // Maps another reference to an allocated frame, the page stays read-only
+    // until it is written to
+    pub fn map_cow(&mut self, page: Page, frame: &Frame, flags: Entry) {
+        memory::share(frame);
+
+        self.map_to(page, Frame { number: frame.number }, (flags - WRITABLE) | COW);
+    }
+
+    // Turns writable mapping into a copy-on-write one, the counterpart of map_cow
+    // for the page already holding a reference
+    pub fn make_cow(&mut self, page: Page) {
+        {
+            let entry = self.p1_entry_mut(&page).expect("Page not mapped with 4KiB page");
+            let frame = entry.frame().expect("Page not mapped");
+            let flags = entry.flags();
+
+            if flags.contains(WRITABLE) {
+                entry.set(frame, (flags - WRITABLE) | COW);
+            }
+        }
+
+        unsafe {
+            x86::tlb::flush(page.address());
+        }
+    }
+
+    // Resolves write to copy-on-write page, last reference to a frame takes it
+    // over without copying. Returns false if the page is not copy-on-write
+    pub fn handle_cow(&mut self, page: Page) -> bool {
+        {
+            let entry = match self.p1_entry_mut(&page) {
+                Some(e) => e,
+                None => return false,
+            };
+
+            if !entry.contains(PRESENT | COW) {
+                return false;
+            }
+
+            let frame = entry.frame().unwrap();
+            let flags = (entry.flags() - COW) | WRITABLE;
+
+            if memory::ref_count(&frame) == 1 {
+                entry.set(frame, flags);
+            } else {
+                let copy = match memory::allocate() {
+                    Some(f) => f,
+                    None => return false,
+                };
+
+                unsafe {
+                    ptr::copy_nonoverlapping(phys_to_virt(frame.address()) as *const u8,
+                                             phys_to_virt(copy.address()) as *mut u8,
+                                             PAGE_SIZE);
+                }
+
+                entry.set(copy, flags);
+
+                memory::deallocate(frame);
+            }
+        }
+
+        unsafe {
+            x86::tlb::flush(page.address());
+        }
+
+        true
+    }
+
+    pub fn update_flags(&mut self, page: Page, flags: Entry) {
+        {
+            let p1 = self.p1_split_mut(&page).expect("Page not mapped");
+            let entry = &mut p1[page.p1_index()];
+            let frame = entry.frame().expect("Page not mapped");
+
+            entry.set(frame, flags | PRESENT);
+        }
+
+        unsafe {
+            x86::tlb::flush(page.address());
+        }
+    }
+
+    // Returns frame that was mapped at the page, it is up to the caller to release it
+    pub fn unmap(&mut self, page: Page) -> Option<Frame> {
+        let frame = {
+            let p1 = match self.p1_split_mut(&page) {
+                Some(p1) => p1,
+                None => return None,
+            };
+
+            let entry = &mut p1[page.p1_index()];
+
+            let frame = entry.frame();
+            entry.clear();
+            frame
+        };
+
+        self.finish_unmap(&page, frame)
+    }
+
+    fn finish_unmap(&mut self, page: &Page, frame: Option<Frame>) -> Option<Frame> {
+        if frame.is_some() {
+            unsafe {
+                x86::tlb::flush(page.address());
+            }
+
+            self.free_empty_tables(page);
+        }
+
+        frame
+    }
+
+    // Releases page tables on the path to the page which no longer map anything
+    fn free_empty_tables(&mut self, page: &Page) {
+        let p3_empty = match self.p4_mut().next_table_mut(page.p4_index()) {
+            Some(p3) => {
+                let p2_empty = match p3.next_table_mut(page.p3_index()) {
+                    Some(p2) => {
+                        if p2.next_table(page.p2_index()).map_or(false, |p1| p1.is_empty()) {
+                            p2.free_next_table(page.p2_index());
+                        }
+
+                        p2.is_empty()
+                    }
+                    None => false,
+                };
+
+                if p2_empty {
+                    p3.free_next_table(page.p3_index());
+                }
+
+                p3.is_empty()
+            }
+            None => false,
+        };
+
+        // Kernel P3 tables are shared by all page tables so they have to stay
+        if p3_empty && page.p4_index() < KERNEL_ENTRY_START {
+            self.p4_mut().free_next_table(page.p4_index());
+        }
+    }
+
+    // A 1GiB page fits only where the P3 entry is unused, a present one may hold
+    // a table with mappings anywhere in the range
+    pub fn can_map_1g(&self, page: &Page) -> bool {
+        self.p4()
+            .next_table(page.p4_index())
+            .map_or(true, |p3| p3[page.p3_index()].is_unused())
+    }
+
+    pub fn can_map_2m(&self, page: &Page) -> bool {
+        let p3 = match self.p4().next_table(page.p4_index()) {
+            Some(p3) => p3,
+            None => return true,
+        };
+
+        if p3[page.p3_index()].is_unused() {
+            return true;
+        }
+
+        p3.next_table(page.p3_index())
+          .map_or(false, |p2| p2[page.p2_index()].is_unused())
+    }
+
     pub fn translate(&self, virt_addr: VirtAddr) -> Option<PhysAddr> {
         let offset = virt_addr % PAGE_SIZE;
 
@@ -79,7 +408,7 @@ impl Mapper {
             p3.and_then(|p3| {
                 let p3_entry = &p3[page.p3_index()];
 
-                if let Some(start_frame) = p3_entry.frame() {
+                if let Some(start_frame) = p3_entry.huge_frame() {
                     if p3_entry.contains(HUGE_PAGE) {
                         return Some(Frame {
                             number: start_frame.number +
@@ -91,7 +420,7 @@ impl Mapper {
                 if let Some(p2) = p3.next_table(page.p3_index()) {
                     let p2_entry = &p2[page.p2_index()];
 
-                    if let Some(start_frame) = p2_entry.frame() {
+                    if let Some(start_frame) = p2_entry.huge_frame() {
                         if p2_entry.contains(HUGE_PAGE) {
                             return Some(Frame {
                                 number: start_frame.number + page.p1_index(),
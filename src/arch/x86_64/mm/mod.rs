@@ -2,20 +2,45 @@ mod entry;
 mod table;
 mod mapper;
 mod page;
+mod remap;
+mod active_table;
+mod temporary_page;
+mod ioremap;
+mod walker;
+pub mod fault;
 
 use spin::Mutex;
 
 use memory;
 use memory::Frame;
+use multiboot2::BootInformation;
 
 use self::entry::*;
-use self::mapper::Mapper;
+pub use self::mapper::has_1g_pages;
+pub use self::active_table::{ActivePageTable, InactivePageTable};
+pub use self::temporary_page::TemporaryPage;
+pub use self::ioremap::{ioremap, iounmap, CacheType};
+pub use self::walker::Mapping;
 use self::page::Page;
 
 pub type VirtAddr = usize;
 pub type PhysAddr = usize;
 
-static MAPPER: Mutex<Mapper> = Mutex::new(Mapper::new());
+pub const TEMPORARY_PAGE: VirtAddr = 0xffff_fe00_0000_0000;
+
+// All physical memory is mapped here. Until init() only the first GiB is available
+pub const PHYSMAP_BASE: VirtAddr = 0xffff_8000_0000_0000;
+pub const BOOT_PHYSMAP_END: PhysAddr = 0x4000_0000;
+pub const KERNEL_BASE: VirtAddr = 0xffff_ffff_8000_0000;
+
+// Legacy BIOS areas and VGA buffer, mapped write-back in the physmap as a whole
+const LOW_MEMORY_END: PhysAddr = 0x10_0000;
+
+pub const fn phys_to_virt(phys: PhysAddr) -> VirtAddr {
+    phys + PHYSMAP_BASE
+}
+
+static MAPPER: Mutex<ActivePageTable> = Mutex::new(ActivePageTable::new());
 
 pub fn virt_to_phys(virt: VirtAddr) -> Option<PhysAddr> {
     let mapper = MAPPER.lock();
@@ -29,6 +54,25 @@ pub fn map_to(virt: VirtAddr, phys: PhysAddr) {
     mapper.map_to(Page::new(virt), Frame::new(phys), PRESENT | WRITABLE);
 }
 
+// For kernel data like the heap, vmalloc and stacks, never executable
+pub fn map_to_data(virt: VirtAddr, phys: PhysAddr) {
+    let mut mapper = MAPPER.lock();
+
+    mapper.map_to(Page::new(virt), Frame::new(phys), PRESENT | WRITABLE | NO_EXECUTE);
+}
+
+pub fn map_to_2m(virt: VirtAddr, phys: PhysAddr) {
+    let mut mapper = MAPPER.lock();
+
+    mapper.map_to_2m(Page::new(virt), Frame::new(phys), PRESENT | WRITABLE);
+}
+
+pub fn map_to_1g(virt: VirtAddr, phys: PhysAddr) {
+    let mut mapper = MAPPER.lock();
+
+    mapper.map_to_1g(Page::new(virt), Frame::new(phys), PRESENT | WRITABLE);
+}
+
 pub fn identity_map(virt: VirtAddr) {
     let mut mapper = MAPPER.lock();
 
@@ -43,11 +87,78 @@ pub fn map(virt: VirtAddr) {
     mapper.map_to(Page::new(virt), frame, PRESENT | WRITABLE);
 }
 
-pub fn unmap(virt: VirtAddr) {
+// Only removes the mapping, the frame may not belong to the frame allocator
+pub fn unmap(virt: VirtAddr) -> Option<PhysAddr> {
+    let mut mapper = MAPPER.lock();
+
+    mapper.unmap(Page::new(virt)).map(|f| f.address())
+}
+
+// Counterpart of map(), the released frame goes back to the frame allocator.
+// Must only be used for frames that came from it
+pub fn unmap_and_free(virt: VirtAddr) -> Option<PhysAddr> {
+    let frame = MAPPER.lock().unmap(Page::new(virt));
+
+    frame.map(|f| {
+        let addr = f.address();
+        memory::deallocate(f);
+        addr
+    })
+}
+
+// Maps the frame behind src at dst as well, both pages become copy-on-write
+pub fn share_cow(src: VirtAddr, dst: VirtAddr) {
+    let mut mapper = MAPPER.lock();
+
+    let frame = Frame::new(mapper.translate(src).expect("Page not mapped"));
+
+    mapper.make_cow(Page::new(src));
+    mapper.map_cow(Page::new(dst), &frame, NO_EXECUTE);
+}
+
+pub fn unmap_2m(virt: VirtAddr) -> Option<PhysAddr> {
+    let mut mapper = MAPPER.lock();
+
+    mapper.unmap_2m(Page::new(virt)).map(|f| f.address())
+}
+
+pub fn unmap_1g(virt: VirtAddr) -> Option<PhysAddr> {
     let mut mapper = MAPPER.lock();
 
-    mapper.unmap(Page::new(virt));
+    mapper.unmap_1g(Page::new(virt)).map(|f| f.address())
+}
+
+fn page_size_name(size: usize) -> &'static str {
+    match size {
+        page::PAGE_1G_SIZE => "1G",
+        page::PAGE_2M_SIZE => "2M",
+        _ => "4K",
+    }
 }
 
-pub fn init() {
+pub fn dump_mappings() {
+    let mapper = MAPPER.lock();
+
+    println!("Page mappings:");
+    mapper.walk(|m| {
+        // HUGE_PAGE left in the flags is the PAT bit of a 4KiB page
+        println!("  0x{:x}-0x{:x} -> 0x{:x} {}{}{}{}{}{}{}{}",
+                 m.virt,
+                 m.virt + m.size,
+                 m.phys,
+                 page_size_name(m.page_size),
+                 if m.flags.contains(WRITABLE) { " W" } else { "" },
+                 if m.flags.contains(USER) { " U" } else { "" },
+                 if m.flags.contains(GLOBAL) { " G" } else { "" },
+                 if m.flags.contains(NO_EXECUTE) { " NX" } else { "" },
+                 if m.flags.contains(WRT_THROUGH) { " PWT" } else { "" },
+                 if m.flags.contains(NO_CACHE) { " PCD" } else { "" },
+                 if m.flags.contains(HUGE_PAGE) { " PAT" } else { "" });
+    });
+}
+
+pub fn init(boot_info: &BootInformation) {
+    ioremap::init();
+
+    remap::remap_kernel(&mut MAPPER.lock(), boot_info);
 }
@@ -1,6 +1,9 @@
 use memory::PAGE_SIZE;
 use super::VirtAddr;
 
+pub const PAGE_2M_SIZE: usize = 0x20_0000;
+pub const PAGE_1G_SIZE: usize = 0x4000_0000;
+
 pub struct Page {
     number: usize,
 }
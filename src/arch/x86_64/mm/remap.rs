@@ -0,0 +1,174 @@
+use core::cmp::min;
+
+use cmdline;
+use memory;
+use memory::{Frame, PAGE_SIZE};
+use multiboot2::{BootInformation, ElfSection, ElfSectionFlags, MemoryAreaType};
+use super::{TEMPORARY_PAGE, KERNEL_BASE, PHYSMAP_BASE, LOW_MEMORY_END};
+use super::active_table::{ActivePageTable, InactivePageTable};
+use super::entry::*;
+use super::mapper::{Mapper, has_1g_pages, KERNEL_ENTRY_START, RECURSIVE_ENTRY};
+use super::page::{Page, PAGE_2M_SIZE, PAGE_1G_SIZE};
+use super::table::ENTRY_CNT;
+use super::temporary_page::TemporaryPage;
+use x86;
+
+extern "C" {
+    // Page below the boot stack
+    static stack_guard: u8;
+}
+
+const EFER_NXE: u64 = 1 << 11;
+const CR0_WP: u64 = 1 << 16;
+
+fn section_flags(section: &ElfSection) -> Entry {
+    let mut flags = PRESENT;
+
+    if section.has_flag(ElfSectionFlags::Writable) {
+        flags.insert(WRITABLE);
+    }
+
+    if !section.has_flag(ElfSectionFlags::Executable) {
+        flags.insert(NO_EXECUTE);
+    }
+
+    flags
+}
+
+unsafe fn enable_nxe() {
+    let efer = x86::msr::rdmsr(x86::msr::IA32_EFER);
+
+    x86::msr::wrmsr(x86::msr::IA32_EFER, efer | EFER_NXE);
+}
+
+unsafe fn enable_write_protect() {
+    x86::controlregs::cr0_write(x86::controlregs::cr0() | CR0_WP);
+}
+
+// Maps physical range at PHYSMAP_BASE using the biggest pages possible
+fn map_physical_range(mapper: &mut Mapper, start: usize, end: usize) {
+    let use_1g = has_1g_pages();
+    let flags = WRITABLE | NO_EXECUTE;
+
+    let mut addr = start & !(PAGE_SIZE - 1);
+    let end = (end + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+
+    while addr < end {
+        let virt = PHYSMAP_BASE + addr;
+
+        // Areas reported by the bootloader might overlap
+        if mapper.translate(virt).is_some() {
+            addr += PAGE_SIZE;
+            continue;
+        }
+
+        // Pages mapped anywhere in the range of a huge page leave its entry
+        // present, smaller pages fill the gaps then
+        if use_1g && addr % PAGE_1G_SIZE == 0 && addr + PAGE_1G_SIZE <= end &&
+           mapper.can_map_1g(&Page::new(virt)) {
+            mapper.map_to_1g(Page::new(virt), Frame::new(addr), flags);
+            addr += PAGE_1G_SIZE;
+        } else if addr % PAGE_2M_SIZE == 0 && addr + PAGE_2M_SIZE <= end &&
+           mapper.can_map_2m(&Page::new(virt)) {
+            mapper.map_to_2m(Page::new(virt), Frame::new(addr), flags);
+            addr += PAGE_2M_SIZE;
+        } else {
+            mapper.map_to(Page::new(virt), Frame::new(addr), flags);
+            addr += PAGE_SIZE;
+        }
+    }
+}
+
+pub fn remap_kernel(active: &mut ActivePageTable, boot_info: &BootInformation) {
+    unsafe {
+        enable_nxe();
+        enable_write_protect();
+    }
+
+    let elf_sections_tag = boot_info.elf_sections_tag().expect("Elf-sections tag required");
+    let memory_map_tag = boot_info.memory_map_tag().expect("Memory map tag required");
+
+    // Boot code is only needed until we jump to the higher half so it is left out
+    let allocated = || {
+        elf_sections_tag.sections()
+                        .filter(|s| s.has_flag(ElfSectionFlags::Allocated))
+                        .filter(|s| s.addr as usize >= KERNEL_BASE)
+    };
+
+    let kernel_start = allocated().map(|s| s.addr as usize).min().unwrap() & !(PAGE_SIZE - 1);
+    let kernel_end = allocated().map(|s| (s.addr + s.size) as usize).max().unwrap();
+
+    let mut temporary_page = TemporaryPage::new(TEMPORARY_PAGE);
+
+    let mut new_table = {
+        let frame = memory::allocate().expect("Out of memory");
+
+        InactivePageTable::new(frame, active, &mut temporary_page)
+    };
+
+    active.with(&mut new_table, &mut temporary_page, |mapper| {
+        // Boot tables map the kernel and the first GiB of the physmap with huge
+        // pages, don't share them
+        for &virt in [KERNEL_BASE, PHYSMAP_BASE].iter() {
+            mapper.p4_mut()[Page::new(virt).p4_index()].clear();
+        }
+
+        // Page tables created later copy the kernel P4 entries, so all of them
+        // get their P3 tables now and mappings added to any of them are shared
+        for idx in (KERNEL_ENTRY_START..ENTRY_CNT).filter(|&i| i != RECURSIVE_ENTRY) {
+            mapper.p4_mut().next_table_create(idx);
+        }
+
+        map_physical_range(mapper, 0, LOW_MEMORY_END);
+
+        // Firmware areas are included so ACPI tables can be read through the
+        // physmap, usable memory past the mem= limit is left out
+        let mem_limit = cmdline::options().mem.unwrap_or(usize::max_value());
+
+        for area in memory_map_tag.all_areas() {
+            let start = area.base_addr as usize;
+            let end = (area.base_addr + area.length) as usize;
+
+            match area.typ() {
+                MemoryAreaType::Available => {
+                    map_physical_range(mapper, min(start, mem_limit), min(end, mem_limit))
+                }
+                MemoryAreaType::AcpiReclaimable |
+                MemoryAreaType::AcpiNvs => map_physical_range(mapper, start, end),
+                _ => {}
+            }
+        }
+
+        let guard = unsafe { &stack_guard as *const _ as usize };
+
+        // Sections sharing a page get the union of their permissions
+        for addr in (kernel_start..kernel_end).step_by(PAGE_SIZE).filter(|&a| a != guard) {
+            let mut sections = allocated().filter(|s| {
+                s.addr as usize <= addr + PAGE_SIZE - 1 && addr < (s.addr + s.size) as usize
+            });
+
+            if let Some(first) = sections.next() {
+                let flags = sections.fold(section_flags(first), |mut flags, s| {
+                    let other = section_flags(s);
+
+                    if other.contains(WRITABLE) {
+                        flags.insert(WRITABLE);
+                    }
+
+                    if !other.contains(NO_EXECUTE) {
+                        flags.remove(NO_EXECUTE);
+                    }
+
+                    flags
+                });
+
+                mapper.map_to(Page::new(addr), Frame::new(addr - KERNEL_BASE), flags);
+            }
+        }
+    });
+
+    let old_table = active.switch(new_table);
+
+    println!("Kernel remapped, old P4 at 0x{:x}",
+             old_table.p4_frame().address());
+}
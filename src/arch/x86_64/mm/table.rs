@@ -2,7 +2,10 @@ use core::marker::PhantomData;
 use core::ops::{Index, IndexMut};
 
 use memory;
+use memory::stats;
+use memory::stats::Usage;
 use arch::mm::entry::*;
+use x86;
 
 pub const ENTRY_CNT: usize = 512;
 
@@ -19,6 +22,10 @@ impl<L> Table<L> where L: TableLevel {
             entry.clear();
         }
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(|e| e.is_unused())
+    }
 }
 
 impl<L> Table<L> where L: HierarchicalLevel {
@@ -28,8 +35,14 @@ impl<L> Table<L> where L: HierarchicalLevel {
 
         if e.contains(PRESENT) && !e.contains(HUGE_PAGE) {
             let addr = self as *const _ as usize;
+            let next = ((addr << 9) | idx << 12) & 0x0000_ffff_ffff_f000;
+
+            // Keep the address canonical, recursive entry is not the last one
+            if next & (1 << 47) != 0 {
+                return Some(next | 0xffff_0000_0000_0000);
+            }
 
-            return Some((addr << 9) | idx << 12);
+            return Some(next);
         }
 
         None
@@ -56,9 +69,14 @@ impl<L> Table<L> where L: HierarchicalLevel {
     }
 
     pub fn next_table_create(&mut self, idx: usize) -> &mut Table<L::NextLevel> {
+        assert!(!self.entries[idx].contains(HUGE_PAGE),
+                "Creating table in place of a huge page");
+
         if self.next_table_addr(idx).is_none() {
             let frame = memory::allocate().expect("Out of memory");
 
+            stats::add(Usage::PageTables, 1);
+
             self.entries[idx].set(frame, PRESENT | WRITABLE);
 
             self.next_table_mut(idx).unwrap().clear();
@@ -66,6 +84,21 @@ impl<L> Table<L> where L: HierarchicalLevel {
 
         self.next_table_mut(idx).unwrap()
     }
+
+    pub fn free_next_table(&mut self, idx: usize) {
+        let addr = self.next_table_addr(idx).expect("No table to free");
+        let frame = self.entries[idx].frame().unwrap();
+
+        self.entries[idx].clear();
+
+        unsafe {
+            x86::tlb::flush(addr);
+        }
+
+        memory::deallocate(frame);
+
+        stats::sub(Usage::PageTables, 1);
+    }
 }
 
 
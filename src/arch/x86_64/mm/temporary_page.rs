@@ -0,0 +1,37 @@
+use memory::Frame;
+use super::VirtAddr;
+use super::active_table::ActivePageTable;
+use super::entry::*;
+use super::page::Page;
+use super::table::{Table, TableLevel};
+
+// Page used to access frames which are not mapped anywhere, like tables of an
+// inactive P4
+pub struct TemporaryPage {
+    addr: VirtAddr,
+}
+
+impl TemporaryPage {
+    pub fn new(addr: VirtAddr) -> TemporaryPage {
+        TemporaryPage { addr: addr }
+    }
+
+    pub fn map(&mut self, frame: Frame, active: &mut ActivePageTable) -> VirtAddr {
+        assert!(active.translate(self.addr).is_none(),
+                "Temporary page already mapped");
+
+        active.map_to(Page::new(self.addr), frame, PRESENT | WRITABLE | NO_EXECUTE);
+
+        self.addr
+    }
+
+    pub fn map_table_frame<L>(&mut self, frame: Frame, active: &mut ActivePageTable) -> &mut Table<L>
+        where L: TableLevel
+    {
+        unsafe { &mut *(self.map(frame, active) as *mut Table<L>) }
+    }
+
+    pub fn unmap(&mut self, active: &mut ActivePageTable) {
+        active.unmap(Page::new(self.addr));
+    }
+}
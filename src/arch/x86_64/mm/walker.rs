@@ -0,0 +1,127 @@
+use memory::PAGE_SIZE;
+use super::{VirtAddr, PhysAddr};
+use super::entry::*;
+use super::mapper::{Mapper, RECURSIVE_ENTRY};
+use super::page::{PAGE_2M_SIZE, PAGE_1G_SIZE};
+use super::table::ENTRY_CNT;
+
+// Contiguous range mapped with pages of the same size and flags
+#[derive(Debug, Clone, Copy)]
+pub struct Mapping {
+    pub virt: VirtAddr,
+    pub phys: PhysAddr,
+    pub size: usize,
+    pub page_size: usize,
+    // Leaf entry flags without ACCESSED and DIRTY, HUGE_PAGE being the PAT bit
+    pub flags: Entry,
+}
+
+impl Mapping {
+    fn extends(&self, other: &Mapping) -> bool {
+        self.page_size == other.page_size && self.flags == other.flags &&
+        self.virt + self.size == other.virt && self.phys + self.size == other.phys
+    }
+}
+
+fn virt_addr(p4: usize, p3: usize, p2: usize, p1: usize) -> VirtAddr {
+    let addr = p4 << 39 | p3 << 30 | p2 << 21 | p1 << 12;
+
+    if addr & (1 << 47) != 0 {
+        addr | 0xffff_0000_0000_0000
+    } else {
+        addr
+    }
+}
+
+fn leaf(virt: VirtAddr, entry: &Entry, page_size: usize) -> Mapping {
+    // In P1 entries HUGE_PAGE is the PAT bit and is reported as is, huge entries
+    // have the PAT bit at 12 and are reported the same way
+    let (frame, mut flags) = if page_size == PAGE_SIZE {
+        (entry.frame(), entry.flags())
+    } else {
+        let mut flags = entry.huge_flags() - HUGE_PAGE;
+
+        if flags.contains(HUGE_PAT) {
+            flags.remove(HUGE_PAT);
+            flags.insert(HUGE_PAGE);
+        }
+
+        (entry.huge_frame(), flags)
+    };
+
+    flags.remove(ACCESSED | DIRTY);
+
+    Mapping {
+        virt: virt,
+        phys: frame.unwrap().address(),
+        size: page_size,
+        page_size: page_size,
+        flags: flags,
+    }
+}
+
+impl Mapper {
+    // Calls f with every present leaf entry in address order, the recursive
+    // mapping is skipped
+    fn walk_pages<F: FnMut(Mapping)>(&self, mut f: F) {
+        let p4 = self.p4();
+
+        for i4 in (0..ENTRY_CNT).filter(|&i| i != RECURSIVE_ENTRY) {
+            let p3 = match p4.next_table(i4) {
+                Some(p3) => p3,
+                None => continue,
+            };
+
+            for i3 in 0..ENTRY_CNT {
+                if p3[i3].contains(PRESENT | HUGE_PAGE) {
+                    f(leaf(virt_addr(i4, i3, 0, 0), &p3[i3], PAGE_1G_SIZE));
+                    continue;
+                }
+
+                let p2 = match p3.next_table(i3) {
+                    Some(p2) => p2,
+                    None => continue,
+                };
+
+                for i2 in 0..ENTRY_CNT {
+                    if p2[i2].contains(PRESENT | HUGE_PAGE) {
+                        f(leaf(virt_addr(i4, i3, i2, 0), &p2[i2], PAGE_2M_SIZE));
+                        continue;
+                    }
+
+                    let p1 = match p2.next_table(i2) {
+                        Some(p1) => p1,
+                        None => continue,
+                    };
+
+                    for i1 in (0..ENTRY_CNT).filter(|&i| p1[i].contains(PRESENT)) {
+                        f(leaf(virt_addr(i4, i3, i2, i1), &p1[i1], PAGE_SIZE));
+                    }
+                }
+            }
+        }
+    }
+
+    // Calls f with every mapped range, neighbouring pages are merged when both
+    // addresses are contiguous and the flags match
+    pub fn walk<F: FnMut(&Mapping)>(&self, mut f: F) {
+        let mut current: Option<Mapping> = None;
+
+        self.walk_pages(|page| {
+            if let Some(ref mut range) = current {
+                if range.extends(&page) {
+                    range.size += page.size;
+                    return;
+                }
+
+                f(range);
+            }
+
+            current = Some(page);
+        });
+
+        if let Some(ref range) = current {
+            f(range);
+        }
+    }
+}
@@ -3,3 +3,5 @@ pub mod interrupts;
 pub mod cpuio;
 pub mod acpi;
 pub mod mm;
+pub mod task;
+pub mod backtrace;
@@ -0,0 +1,83 @@
+use core::mem::size_of;
+
+use arch::mm::VirtAddr;
+use memory::PAGE_SIZE;
+use memory::stack;
+use x86;
+use x86::segmentation::SegmentSelector;
+use x86::task::TaskStateSegment;
+
+// Interrupt stack table slot for double faults. A page fault hitting a guard
+// page can't push its frame and escalates, so the double fault handler is the
+// one which needs a working stack. Other faults stay on the current stack as
+// nested ones would overwrite the frame on a shared IST stack
+pub const DOUBLE_FAULT_IST: u8 = 1;
+
+const IST_STACK_PAGES: usize = 4;
+
+// Slot reserved for the TSS descriptor in gdt64 (boot.asm)
+const TSS_GDT_INDEX: usize = 3;
+
+const TSS_AVAILABLE: u64 = 0x9;
+const DESC_PRESENT: u64 = 1 << 47;
+
+extern "C" {
+    static mut gdt64: [u64; 5];
+}
+
+static mut TSS: TaskStateSegment = TaskStateSegment {
+    reserved: 0,
+    rsp: [0; 3],
+    reserved2: 0,
+    ist: [0; 7],
+    reserved3: 0,
+    reserved4: 0,
+    iomap_base: 0,
+};
+
+pub fn init() {
+    unsafe {
+        // IST stacks are never released
+        let stack = stack::allocate(IST_STACK_PAGES).expect("Out of memory");
+
+        TSS.ist[DOUBLE_FAULT_IST as usize - 1] = stack.top() as u64;
+
+        // No I/O permission bitmap
+        TSS.iomap_base = size_of::<TaskStateSegment>() as u16;
+
+        let base = &TSS as *const _ as u64;
+        let limit = (size_of::<TaskStateSegment>() - 1) as u64;
+
+        gdt64[TSS_GDT_INDEX] = (limit & 0xffff) | (base & 0xff_ffff) << 16 |
+                               TSS_AVAILABLE << 40 | DESC_PRESENT |
+                               (limit >> 16 & 0xf) << 48 | (base >> 24 & 0xff) << 56;
+        gdt64[TSS_GDT_INDEX + 1] = base >> 32;
+
+        x86::task::load_ltr(SegmentSelector::new(TSS_GDT_INDEX as u16));
+    }
+}
+
+// Bottom and top of the IST stack holding the address. Reads the TSS directly,
+// the stacks are set once in init() and never change
+pub fn ist_stack_bounds(virt: VirtAddr) -> Option<(VirtAddr, VirtAddr)> {
+    let ist = unsafe { TSS.ist };
+
+    ist.iter()
+       .filter(|&&top| top != 0)
+       .map(|&top| (top as VirtAddr - IST_STACK_PAGES * PAGE_SIZE, top as VirtAddr))
+       .find(|&(bottom, top)| virt >= bottom && virt < top)
+}
+
+// Continues in f(arg) on the given stack, the current one is abandoned. The
+// frame pointer chain starts over so backtraces end there
+pub unsafe fn switch_stack(top: VirtAddr, f: extern "C" fn(usize) -> !, arg: usize) -> ! {
+    asm!("mov rsp, $0
+          xor rbp, rbp
+          call $1"
+         :
+         : "r"(top), "r"(f), "{rdi}"(arg)
+         : "memory"
+         : "intel", "volatile");
+
+    unreachable!();
+}
@@ -0,0 +1,166 @@
+use spin::Mutex;
+
+use multiboot2::{BootInformation, StrError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Console {
+    // VGA text buffer
+    Vga,
+    // Linear framebuffer if the boot loader set one up, VGA otherwise
+    Framebuffer,
+    // COM1
+    Serial,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub loglevel: LogLevel,
+    pub console: Console,
+    // Skip ACPI table parsing
+    pub noacpi: bool,
+    // Memory above this physical address is ignored
+    pub mem: Option<usize>,
+}
+
+const DEFAULT: Options = Options {
+    loglevel: LogLevel::Info,
+    console: Console::Framebuffer,
+    noacpi: false,
+    mem: None,
+};
+
+static OPTIONS: Mutex<Options> = Mutex::new(DEFAULT);
+
+// Requested and used mem= value if the limit had to be raised
+static MEM_RAISED: Mutex<Option<(usize, usize)>> = Mutex::new(None);
+
+// Kept for print_warnings(), init() runs before any console is set up
+static COMMAND_LINE: Mutex<Option<Result<&'static str, StrError>>> = Mutex::new(None);
+
+pub fn options() -> Options {
+    *OPTIONS.lock()
+}
+
+fn parse_loglevel(value: &str) -> Option<LogLevel> {
+    match value {
+        "error" => Some(LogLevel::Error),
+        "warn" => Some(LogLevel::Warn),
+        "info" => Some(LogLevel::Info),
+        "debug" => Some(LogLevel::Debug),
+        _ => None,
+    }
+}
+
+fn parse_console(value: &str) -> Option<Console> {
+    match value {
+        "vga" => Some(Console::Vga),
+        "framebuffer" => Some(Console::Framebuffer),
+        "serial" => Some(Console::Serial),
+        _ => None,
+    }
+}
+
+// Number of bytes with an optional K, M or G suffix
+fn parse_size(value: &str) -> Option<usize> {
+    let (digits, shift) = match value.as_bytes().last() {
+        Some(&b'K') | Some(&b'k') => (&value[..value.len() - 1], 10),
+        Some(&b'M') | Some(&b'm') => (&value[..value.len() - 1], 20),
+        Some(&b'G') | Some(&b'g') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+
+    digits.parse::<usize>()
+          .ok()
+          .and_then(|n| n.checked_mul(1 << shift))
+}
+
+fn parse_option(options: &mut Options, key: &str, value: Option<&str>) -> bool {
+    match (key, value) {
+        ("loglevel", Some(v)) => parse_loglevel(v).map(|l| options.loglevel = l).is_some(),
+        ("console", Some(v)) => parse_console(v).map(|c| options.console = c).is_some(),
+        ("mem", Some(v)) => parse_size(v).map(|m| options.mem = Some(m)).is_some(),
+        ("noacpi", None) => {
+            options.noacpi = true;
+            true
+        }
+        _ => false,
+    }
+}
+
+fn parse_arg(options: &mut Options, arg: &str) -> bool {
+    let mut parts = arg.splitn(2, '=');
+    let key = parts.next().unwrap();
+    let value = parts.next();
+
+    parse_option(options, key, value)
+}
+
+// Unknown or invalid options are skipped, print_warnings() reports them
+pub fn parse(cmdline: &str) -> Options {
+    let mut options = DEFAULT;
+
+    for arg in cmdline.split_whitespace() {
+        parse_arg(&mut options, arg);
+    }
+
+    options
+}
+
+// Options stay at their defaults without a command line tag
+pub fn init(boot_info: &BootInformation) {
+    let cmdline = match boot_info.command_line_tag() {
+        Some(tag) => tag.command_line(),
+        None => return,
+    };
+
+    if let Ok(line) = cmdline {
+        *OPTIONS.lock() = parse(line);
+    }
+
+    *COMMAND_LINE.lock() = Some(cmdline);
+}
+
+// Moves the mem= limit up to at least the given address
+pub fn raise_mem(min: usize) {
+    let mut options = OPTIONS.lock();
+    let current = options.mem;
+
+    if let Some(mem) = current {
+        if mem < min {
+            options.mem = Some(min);
+            *MEM_RAISED.lock() = Some((mem, min));
+        }
+    }
+}
+
+// Reports whatever init() had to ignore, meant to be called once the console is up
+pub fn print_warnings() {
+    match *COMMAND_LINE.lock() {
+        Some(Ok(cmdline)) => {
+            let mut options = DEFAULT;
+
+            for arg in cmdline.split_whitespace() {
+                if !parse_arg(&mut options, arg) {
+                    println!("cmdline: ignoring unknown or invalid option {}", arg);
+                }
+            }
+        }
+        Some(Err(StrError::Utf8(_))) => println!("cmdline: command line is not valid UTF-8"),
+        Some(Err(StrError::InvalidSize)) => println!("cmdline: invalid command line tag"),
+        None => {}
+    }
+
+    if let Some((mem, used)) = *MEM_RAISED.lock() {
+        println!("cmdline: mem=0x{:x} would cut off the kernel or boot data, using 0x{:x}",
+                 mem,
+                 used);
+    }
+}
@@ -0,0 +1,152 @@
+use core::cmp::{max, min};
+use core::mem::size_of;
+use core::slice;
+use core::str;
+use spin::Mutex;
+
+use arch::mm::phys_to_virt;
+use multiboot2::{BootInformation, ElfSection, ElfSectionFlags, ElfSectionType, ElfSectionsTag};
+
+// Set by init() so symbols can be resolved without the boot information at hand
+static SECTIONS: Mutex<Option<&'static ElfSectionsTag>> = Mutex::new(None);
+
+const STT_FUNC: u8 = 2;
+
+#[repr(C)]
+struct ElfSymbol {
+    name: u32,
+    info: u8,
+    other: u8,
+    shndx: u16,
+    value: u64,
+    size: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol {
+    pub name: &'static str,
+    pub addr: usize,
+    pub size: usize,
+    pub is_function: bool,
+}
+
+// Allocated sections are mapped at their link address, the boot loader copies the
+// others (symbol and string tables) to free memory and stores the physical address
+fn section_data(section: &ElfSection) -> &'static [u8] {
+    let addr = if section.has_flag(ElfSectionFlags::Allocated) {
+        section.addr as usize
+    } else {
+        phys_to_virt(section.addr as usize)
+    };
+
+    unsafe { slice::from_raw_parts(addr as *const u8, section.size as usize) }
+}
+
+// Null terminated string at the given offset of a string table
+fn table_str(table: &'static [u8], offset: usize) -> Option<&'static str> {
+    if offset >= table.len() {
+        return None;
+    }
+
+    let bytes = &table[offset..];
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+
+    str::from_utf8(&bytes[..len]).ok()
+}
+
+pub fn section_name(tag: &'static ElfSectionsTag, section: &ElfSection) -> Option<&'static str> {
+    tag.string_table()
+       .and_then(|strtab| table_str(section_data(strtab), section.name_index() as usize))
+}
+
+fn symbol_table(tag: &'static ElfSectionsTag) -> Option<&'static ElfSection> {
+    tag.sections().find(|s| {
+        s.section_type() == Some(ElfSectionType::LinkerSymbolTable) &&
+        s.entry_size() as usize == size_of::<ElfSymbol>()
+    })
+}
+
+pub struct SymbolIter {
+    symbols: &'static [ElfSymbol],
+    strtab: &'static [u8],
+    index: usize,
+}
+
+impl Iterator for SymbolIter {
+    type Item = Symbol;
+
+    fn next(&mut self) -> Option<Symbol> {
+        while self.index < self.symbols.len() {
+            let symbol = &self.symbols[self.index];
+            self.index += 1;
+
+            // Entry 0 and section or file symbols have no name
+            if let Some(name) = table_str(self.strtab, symbol.name as usize) {
+                if !name.is_empty() {
+                    return Some(Symbol {
+                        name: name,
+                        addr: symbol.value as usize,
+                        size: symbol.size as usize,
+                        is_function: symbol.info & 0xf == STT_FUNC,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+pub fn symbols(tag: &'static ElfSectionsTag) -> Option<SymbolIter> {
+    let symtab = match symbol_table(tag) {
+        Some(s) => s,
+        None => return None,
+    };
+
+    tag.section(symtab.link()).map(|strtab| {
+        let data = section_data(symtab);
+
+        SymbolIter {
+            symbols: unsafe {
+                slice::from_raw_parts(data.as_ptr() as *const ElfSymbol,
+                                      data.len() / size_of::<ElfSymbol>())
+            },
+            strtab: section_data(strtab),
+            index: 0,
+        }
+    })
+}
+
+// Physical range of the symbol table and the string tables, memory::init keeps
+// it out of the frame allocator so symbols can be resolved later on
+pub fn tables_range(tag: &'static ElfSectionsTag) -> Option<(usize, usize)> {
+    let symtab = symbol_table(tag);
+    let strtab = symtab.and_then(|s| tag.section(s.link()));
+
+    [symtab, strtab, tag.string_table()]
+        .iter()
+        .filter_map(|s| *s)
+        .filter(|s| !s.has_flag(ElfSectionFlags::Allocated) && s.addr != 0)
+        .map(|s| (s.addr as usize, (s.addr + s.size) as usize))
+        .fold(None, |range, (start, end)| {
+            Some(range.map_or((start, end), |(s, e)| (min(s, start), max(e, end))))
+        })
+}
+
+pub fn init(boot_info: &BootInformation) {
+    *SECTIONS.lock() = boot_info.elf_sections_tag();
+}
+
+// Function containing the address and the offset into it, None as well if the
+// sections are locked
+pub fn resolve(addr: usize) -> Option<(&'static str, usize)> {
+    let tag = match SECTIONS.try_lock().and_then(|sections| *sections) {
+        Some(tag) => tag,
+        None => return None,
+    };
+
+    symbols(tag).and_then(|mut symbols| {
+        symbols.find(|s| s.is_function && addr >= s.addr && addr < s.addr + s.size)
+               .map(|s| (s.name, addr - s.addr))
+    })
+}
@@ -0,0 +1,197 @@
+use core::fmt;
+use core::ptr;
+use spin::Mutex;
+
+use arch::mm::{ioremap, CacheType, VirtAddr};
+use font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+use multiboot2::{BootInformation, ColorField, FramebufferType};
+
+// Set by init() if the boot loader left a linear framebuffer, print! uses the VGA
+// text buffer otherwise
+pub static CONSOLE: Mutex<Option<FramebufferWriter>> = Mutex::new(None);
+
+// Same colors as the VGA console, LightGreen on Black
+const FOREGROUND: (u8, u8, u8) = (0x55, 0xff, 0x55);
+const BACKGROUND: (u8, u8, u8) = (0x00, 0x00, 0x00);
+
+pub struct FramebufferWriter {
+    buffer: VirtAddr,
+    pitch: usize,
+    bytes_per_pixel: usize,
+    columns: usize,
+    rows: usize,
+    column: usize,
+    row: usize,
+    foreground: u32,
+    background: u32,
+}
+
+// The value ends up in the top bits of fields wider than 8 bits
+fn encode(value: u8, field: ColorField) -> u32 {
+    let value = value as u32;
+
+    if field.size <= 8 {
+        (value >> (8 - field.size)) << field.position
+    } else {
+        (value << (field.size - 8)) << field.position
+    }
+}
+
+fn encode_rgb(rgb: (u8, u8, u8), red: ColorField, green: ColorField, blue: ColorField) -> u32 {
+    encode(rgb.0, red) | encode(rgb.1, green) | encode(rgb.2, blue)
+}
+
+impl FramebufferWriter {
+    pub fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            byte => {
+                if self.column >= self.columns {
+                    self.new_line();
+                }
+
+                let (row, col) = (self.row, self.column);
+                self.draw_glyph(row, col, byte);
+                self.column += 1;
+            }
+        }
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: u32) {
+        let addr = self.buffer + y * self.pitch + x * self.bytes_per_pixel;
+
+        unsafe {
+            if self.bytes_per_pixel == 4 {
+                ptr::write_volatile(addr as *mut u32, color);
+            } else {
+                for i in 0..self.bytes_per_pixel {
+                    ptr::write_volatile((addr + i) as *mut u8, (color >> (i * 8)) as u8);
+                }
+            }
+        }
+    }
+
+    fn draw_glyph(&mut self, row: usize, col: usize, byte: u8) {
+        let glyph = font::glyph(byte);
+        let (x, y) = (col * GLYPH_WIDTH, row * GLYPH_HEIGHT);
+
+        for (dy, bits) in glyph.iter().enumerate() {
+            for dx in 0..GLYPH_WIDTH {
+                let color = if bits & (1 << dx) != 0 {
+                    self.foreground
+                } else {
+                    self.background
+                };
+
+                self.put_pixel(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        let background = self.background;
+
+        for y in (row * GLYPH_HEIGHT)..((row + 1) * GLYPH_HEIGHT) {
+            for x in 0..(self.columns * GLYPH_WIDTH) {
+                self.put_pixel(x, y, background);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        for row in 0..self.rows {
+            self.clear_row(row);
+        }
+
+        self.row = 0;
+        self.column = 0;
+    }
+
+    fn scroll(&mut self) {
+        let row_bytes = self.pitch * GLYPH_HEIGHT;
+
+        unsafe {
+            ptr::copy((self.buffer + row_bytes) as *const u8,
+                      self.buffer as *mut u8,
+                      row_bytes * (self.rows - 1));
+        }
+
+        let last = self.rows - 1;
+        self.clear_row(last);
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+}
+
+impl fmt::Write for FramebufferWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.write_byte(byte)
+        }
+
+        Ok(())
+    }
+}
+
+// Only direct color framebuffers with 24 or 32 bits per pixel are supported, EGA
+// text mode is handled by the VGA console
+pub fn init(boot_info: &BootInformation) {
+    let tag = match boot_info.framebuffer_tag() {
+        Some(tag) => tag,
+        None => return,
+    };
+
+    let (red, green, blue) = match tag.framebuffer_type() {
+        Some(FramebufferType::Rgb { red, green, blue }) => (red, green, blue),
+        _ => return,
+    };
+
+    if tag.bpp != 24 && tag.bpp != 32 {
+        return;
+    }
+
+    // Every field has to fit in a pixel
+    if ![red, green, blue].iter().all(|f| {
+        f.size > 0 && f.position as usize + f.size as usize <= tag.bpp as usize
+    }) {
+        return;
+    }
+
+    let pitch = tag.pitch as usize;
+    let (width, height) = (tag.width as usize, tag.height as usize);
+
+    // Too small for a single line of text
+    if width < GLYPH_WIDTH || height < GLYPH_HEIGHT {
+        return;
+    }
+
+    // Output stays on the VGA console if the framebuffer can't be mapped
+    let buffer = match ioremap(tag.addr as usize, pitch * height, CacheType::WriteCombining) {
+        Some(buffer) => buffer,
+        None => return,
+    };
+
+    let mut writer = FramebufferWriter {
+        buffer: buffer,
+        pitch: pitch,
+        bytes_per_pixel: tag.bpp as usize / 8,
+        columns: width / GLYPH_WIDTH,
+        rows: height / GLYPH_HEIGHT,
+        column: 0,
+        row: 0,
+        foreground: encode_rgb(FOREGROUND, red, green, blue),
+        background: encode_rgb(BACKGROUND, red, green, blue),
+    };
+
+    writer.clear();
+
+    *CONSOLE.lock() = Some(writer);
+}
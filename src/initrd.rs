@@ -0,0 +1,37 @@
+use core::slice;
+
+use arch::mm::phys_to_virt;
+use memory::MAX_MODULES;
+use multiboot2::{BootInformation, ModuleTag};
+
+// Modules stay where the boot loader put them, memory::init keeps the frames of
+// the first MAX_MODULES out of the allocator and the physmap covers them
+pub fn data(module: &ModuleTag) -> &'static [u8] {
+    let start = phys_to_virt(module.start_address());
+    let len = module.end_address() - module.start_address();
+
+    unsafe { slice::from_raw_parts(start as *const u8, len) }
+}
+
+// First module whose command line starts with the given name, GRUB passes the
+// path and arguments given on its module2 line
+pub fn find(boot_info: &BootInformation, name: &str) -> Option<&'static [u8]> {
+    boot_info.module_tags()
+             .take(MAX_MODULES)
+             .find(|module| {
+                 module.command_line()
+                       .ok()
+                       .and_then(|cmdline| cmdline.split_whitespace().next())
+                       .map_or(false, |first| first == name)
+             })
+             .map(data)
+}
+
+pub fn print_modules(boot_info: &BootInformation) {
+    for module in boot_info.module_tags().take(MAX_MODULES) {
+        println!("Module 0x{:x}-0x{:x}: {}",
+                 module.start_address(),
+                 module.end_address(),
+                 module.command_line().unwrap_or("<invalid>"));
+    }
+}
@@ -1,10 +1,13 @@
 #![feature(lang_items, asm, step_by)]
 #![feature(const_fn, unique)]
 #![feature(associated_type_defaults)]
+#![feature(alloc, oom)]
 #![no_std]
 #![allow(dead_code)]
 
 extern crate rlibc;
+extern crate alloc;
+extern crate heap_allocator;
 extern crate spin;
 
 #[macro_use]
@@ -16,28 +19,50 @@ extern crate x86;
 #[macro_use]
 mod vga;
 
+mod cmdline;
+mod elf;
+mod font;
+mod framebuffer;
+mod initrd;
+mod serial;
 mod multiboot2;
 mod memory;
 pub mod arch;
 
+// Pages of the stack rust_main switches to once memory management is up
+const KERNEL_STACK_PAGES: usize = 16;
+
 fn print_kernel_sections(boot_info: &multiboot2::BootInformation) {
     let elf_sections_tag = boot_info.elf_sections_tag().expect("Elf-sections tag required");
     println!("Kernel sections:");
-    for (idx, section) in elf_sections_tag.sections().enumerate() {
+    for section in elf_sections_tag.sections() {
         println!("  {} addr: 0x{:x}, size 0x{:x}, flags: 0x{:x}",
-                 idx,
+                 elf::section_name(elf_sections_tag, section).unwrap_or("<unknown>"),
                  section.addr,
                  section.size,
                  section.flags);
     }
 }
 
+// Kernel sections are linked at KERNEL_BASE except the boot code, which stays at
+// its physical address
+fn kernel_phys_addr(addr: u64) -> usize {
+    let addr = addr as usize;
+
+    if addr >= arch::mm::KERNEL_BASE {
+        addr - arch::mm::KERNEL_BASE
+    } else {
+        addr
+    }
+}
+
 fn print_memory_areas(memory_map_tag: &multiboot2::MemoryMapTag) {
     println!("Memory areas:");
-    for area in memory_map_tag.memory_areas() {
-        println!("  start 0x{:x}, length: 0x{:x}",
+    for area in memory_map_tag.all_areas() {
+        println!("  start 0x{:x}, length: 0x{:x}, type: {:?}",
                  area.base_addr,
-                 area.length);
+                 area.length,
+                 area.typ());
     }
 }
 
@@ -45,50 +70,112 @@ fn print_memory_areas(memory_map_tag: &multiboot2::MemoryMapTag) {
 pub extern "C" fn rust_main(multiboot_addr: usize) {
     vga::clear_screen();
 
-    let boot_info = unsafe { multiboot2::load(multiboot_addr) };
+    let boot_info = unsafe { multiboot2::load(arch::mm::phys_to_virt(multiboot_addr)) };
     let memory_map_tag = boot_info.memory_map_tag().expect("Memory map tag required");
     let elf_sections_tag = boot_info.elf_sections_tag().expect("Elf-sections tag required");
 
-    print_memory_areas(memory_map_tag);
+    cmdline::init(boot_info);
+
+    let options = cmdline::options();
+
+    // The framebuffer needs memory management, it is set up after arch::mm::init
+    if options.console == cmdline::Console::Serial {
+        serial::init();
+    }
+
+    if let Some(name) = boot_info.boot_loader_name_tag().and_then(|tag| tag.name().ok()) {
+        println!("Booted by {}", name);
+    }
+
+    if options.loglevel >= cmdline::LogLevel::Info {
+        print_memory_areas(memory_map_tag);
+        initrd::print_modules(boot_info);
+    }
+
+    if options.loglevel >= cmdline::LogLevel::Debug {
+        print_kernel_sections(boot_info);
+    }
 
-    //print_kernel_sections(boot_info);
+    let kernel_sections = || {
+        elf_sections_tag.sections()
+                        .filter(|s| s.has_flag(multiboot2::ElfSectionFlags::Allocated))
+    };
 
-    let kernel_start = elf_sections_tag.sections().map(|s| s.addr).min().unwrap();
-    let kernel_end = elf_sections_tag.sections().map(|s| s.addr + s.size).max().unwrap();
+    let kernel_start = kernel_sections().map(|s| kernel_phys_addr(s.addr)).min().unwrap();
+    let kernel_end = kernel_sections().map(|s| kernel_phys_addr(s.addr + s.size)).max().unwrap();
 
     let multiboot_start = multiboot_addr;
     let multiboot_end = multiboot_start + (boot_info.total_size as usize);
 
-    println!("kernel_start: 0x{:x}, kernel_end: 0x{:x}",
-             kernel_start,
-             kernel_end);
-    println!("multiboot_start: 0x{:x}, multiboot_end: 0x{:x}",
-             multiboot_start,
-             multiboot_end);
+    if options.loglevel >= cmdline::LogLevel::Info {
+        println!("kernel_start: 0x{:x}, kernel_end: 0x{:x}",
+                 kernel_start,
+                 kernel_end);
+        println!("multiboot_start: 0x{:x}, multiboot_end: 0x{:x}",
+                 multiboot_start,
+                 multiboot_end);
+    }
 
     memory::init(kernel_start as usize,
                  kernel_end as usize,
                  multiboot_start as usize,
                  multiboot_end as usize,
-                 memory_map_tag.memory_areas());
-    arch::mm::init();
+                 boot_info.module_tags(),
+                 elf::tables_range(elf_sections_tag),
+                 memory_map_tag);
+    arch::mm::init(boot_info);
+    memory::heap::init();
+    elf::init(boot_info);
+
+    if options.console == cmdline::Console::Framebuffer {
+        framebuffer::init(boot_info);
+    }
+
+    cmdline::print_warnings();
+
+    arch::task::init();
+
+    let stack = memory::stack::allocate(KERNEL_STACK_PAGES).expect("Out of memory");
+
+    unsafe {
+        arch::task::switch_stack(stack.top(), rust_main_stack, multiboot_addr);
+    }
+}
+
+// Continuation of rust_main on the allocated stack, the boot stack is not used anymore
+extern "C" fn rust_main_stack(multiboot_addr: usize) -> ! {
+    let boot_info = unsafe { multiboot2::load(arch::mm::phys_to_virt(multiboot_addr)) };
+    let memory_map_tag = boot_info.memory_map_tag().expect("Memory map tag required");
+
     arch::acpi::init();
+
+    if !arch::acpi::in_use() {
+        memory::reclaim(memory_map_tag.areas_of(multiboot2::MemoryAreaType::AcpiReclaimable));
+    }
     arch::interrupts::init();
 
     /*
-    unsafe {
-        arch::mm::map_to(0xffff_8000_0000_0000, 0x1000000);
-
-        let ptr: *mut u64 = 0xffff_8000_0000_0000 as *mut u64;
+    if let Some(virt) = memory::vmalloc::vmalloc(memory::PAGE_SIZE) {
+        unsafe {
+            let ptr: *mut u64 = virt as *mut u64;
 
-        *ptr = 33;
+            *ptr = 33;
 
-        println!("val: {}", *ptr);
+            println!("val: {}", *ptr);
+        }
 
-        arch::mm::unmap(0xffff_8000_0000_0000);
+        memory::vmalloc::vfree(virt);
     }
     */
 
+    if cmdline::options().loglevel >= cmdline::LogLevel::Info {
+        memory::stats().print();
+    }
+
+    if cmdline::options().loglevel >= cmdline::LogLevel::Debug {
+        memory::slab::print_stats();
+    }
+
     println!("KERNEL END");
 
     unsafe {
@@ -108,5 +195,7 @@ extern "C" fn panic_fmt(fmt: core::fmt::Arguments, file: &str, line: u32) -> ! {
     println!("\n\nPANIC in {} at line {}:", file, line);
     println!("    {}", fmt);
 
+    arch::backtrace::print();
+
     loop {}
 }
@@ -1,38 +1,70 @@
-use memory::{Frame, FrameAllocator};
+use memory::{Frame, FrameAllocator, PAGE_SIZE};
 use multiboot2::{MemoryAreaIter, MemoryArea};
 
-pub struct AreaFrameAllocator {
+pub struct AreaFrameAllocator<'a> {
     next_free_frame: Frame,
     current_area: Option<&'static MemoryArea>,
     areas: MemoryAreaIter,
-    kernel_start: Frame,
-    kernel_end: Frame,
-    multiboot_start: Frame,
-    multiboot_end: Frame,
+    // Frame number ranges [start, end) holding the kernel, boot information and
+    // anything else the boot loader left in available memory
+    reserved: &'a [(usize, usize)],
 }
 
-impl AreaFrameAllocator {
-    pub fn new(kernel_start: usize,
-               kernel_end: usize,
-               multiboot_start: usize,
-               multiboot_end: usize,
+impl<'a> AreaFrameAllocator<'a> {
+    pub fn new(reserved: &'a [(usize, usize)],
                memory_areas: MemoryAreaIter)
-               -> AreaFrameAllocator {
+               -> AreaFrameAllocator<'a> {
 
         let mut allocator = AreaFrameAllocator {
             next_free_frame: Frame::new(0),
             current_area: None,
             areas: memory_areas,
-            kernel_start: Frame::new(kernel_start),
-            kernel_end: Frame::new(kernel_end),
-            multiboot_start: Frame::new(multiboot_start),
-            multiboot_end: Frame::new(multiboot_end),
+            reserved: reserved,
         };
 
         allocator.choose_next_area();
         allocator
     }
 
+    pub fn next_free_frame(&self) -> &Frame {
+        &self.next_free_frame
+    }
+
+    pub fn allocate_contiguous(&mut self, size: usize) -> Option<Frame> {
+        let count = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+
+        let mut first = match self.allocate_frame() {
+            Some(f) => f,
+            None => return None,
+        };
+        let mut allocated = 1;
+
+        while allocated < count {
+            let frame = match self.allocate_frame() {
+                Some(f) => f,
+                None => return None,
+            };
+
+            if frame.number == first.number + allocated {
+                allocated += 1;
+            } else {
+                // Hit a hole, start again from the new frame
+                first = frame;
+                allocated = 1;
+            }
+        }
+
+        Some(first)
+    }
+
+    // First frame past the reserved range the frame belongs to
+    fn reserved_end(&self, frame: &Frame) -> Option<usize> {
+        self.reserved
+            .iter()
+            .find(|&&(start, end)| frame.number >= start && frame.number < end)
+            .map(|&(_, end)| end)
+    }
+
     fn choose_next_area(&mut self) {
         self.current_area = self.areas
                                 .clone()
@@ -52,7 +84,7 @@ impl AreaFrameAllocator {
     }
 }
 
-impl FrameAllocator for AreaFrameAllocator {
+impl<'a> FrameAllocator for AreaFrameAllocator<'a> {
     fn allocate_frame(&mut self) -> Option<Frame> {
         if let Some(area) = self.current_area {
             let frame = Frame { number: self.next_free_frame.number };
@@ -64,10 +96,8 @@ impl FrameAllocator for AreaFrameAllocator {
 
             if frame > current_area_last_frame {
                 self.choose_next_area();
-            } else if frame >= self.kernel_start && frame <= self.kernel_end {
-                self.next_free_frame = Frame { number: self.kernel_end.number + 1 };
-            } else if frame >= self.multiboot_start && frame <= self.multiboot_end {
-                self.next_free_frame = Frame { number: self.multiboot_end.number + 1 };
+            } else if let Some(end) = self.reserved_end(&frame) {
+                self.next_free_frame = Frame { number: end };
             } else {
                 self.next_free_frame.number += 1;
                 return Some(frame);
@@ -79,7 +109,7 @@ impl FrameAllocator for AreaFrameAllocator {
         }
     }
 
-    fn deallocate_frame(&mut self, _frame: Frame) {
-        unimplemented!()
-    }
+    // The bootstrap allocator only hands out frames for good, whatever it gave
+    // away before the zones took over stays allocated, so freed frames leak
+    fn deallocate_frame(&mut self, _frame: Frame) {}
 }
@@ -0,0 +1,61 @@
+use core::slice;
+
+const BITS_PER_ENTRY: usize = 64;
+
+pub struct Bitmap {
+    entries: &'static mut [u64],
+}
+
+impl Bitmap {
+    pub fn entries_for(bits: usize) -> usize {
+        (bits + BITS_PER_ENTRY - 1) / BITS_PER_ENTRY
+    }
+
+    pub fn size_for(bits: usize) -> usize {
+        Bitmap::entries_for(bits) * 8
+    }
+
+    pub unsafe fn new(addr: usize, bits: usize) -> Bitmap {
+        let entries = slice::from_raw_parts_mut(addr as *mut u64, Bitmap::entries_for(bits));
+
+        for entry in entries.iter_mut() {
+            *entry = 0;
+        }
+
+        Bitmap { entries: entries }
+    }
+
+    pub fn test(&self, idx: usize) -> bool {
+        self.entries[idx / BITS_PER_ENTRY] & (1 << (idx % BITS_PER_ENTRY)) != 0
+    }
+
+    pub fn set(&mut self, idx: usize, value: bool) {
+        let entry = &mut self.entries[idx / BITS_PER_ENTRY];
+        let bit = 1 << (idx % BITS_PER_ENTRY);
+
+        if value {
+            *entry |= bit;
+        } else {
+            *entry &= !bit;
+        }
+    }
+
+    // Finds the first set bit in start..end
+    pub fn find_set(&self, start: usize, end: usize) -> Option<usize> {
+        let mut idx = start;
+
+        while idx < end {
+            let entry = self.entries[idx / BITS_PER_ENTRY] >> (idx % BITS_PER_ENTRY);
+
+            if entry == 0 {
+                idx = (idx / BITS_PER_ENTRY + 1) * BITS_PER_ENTRY;
+            } else {
+                let found = idx + entry.trailing_zeros() as usize;
+
+                return if found < end { Some(found) } else { None };
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,304 @@
+use core::cmp::{min, max};
+use core::mem::size_of;
+use core::slice;
+
+use memory::{Frame, FrameAllocator, AreaFrameAllocator, PAGE_SIZE};
+use memory::bitmap::Bitmap;
+use arch::mm::{phys_to_virt, BOOT_PHYSMAP_END};
+use multiboot2::MemoryAreaIter;
+
+// Largest block is 2^MAX_ORDER frames (1 GiB)
+pub const MAX_ORDER: usize = 18;
+
+const ORDER_CNT: usize = MAX_ORDER + 1;
+
+pub struct BuddyFrameAllocator {
+    // Frames in [start, end) are managed, blocks are counted from base which is
+    // aligned to the biggest block so they stay naturally aligned
+    base: usize,
+    start: usize,
+    end: usize,
+    frame_count: usize,
+    // Bit set means the block is free and not merged into a bigger one
+    free: Bitmap,
+    offsets: [usize; ORDER_CNT],
+    free_blocks: [usize; ORDER_CNT],
+    // Number of additional references to each frame, frames shared copy-on-write
+    // are released once the last reference goes away
+    shared: &'static mut [u32],
+}
+
+fn blocks(frame_count: usize, order: usize) -> usize {
+    (frame_count + (1 << order) - 1) >> order
+}
+
+fn align_up(bits: usize) -> usize {
+    (bits + 63) & !63
+}
+
+impl BuddyFrameAllocator {
+    // Manages frames in [start, end), all of them start out allocated
+    pub fn new(bootstrap: &mut AreaFrameAllocator,
+               start: usize,
+               end: usize)
+               -> BuddyFrameAllocator {
+
+        let base = start & !((1 << MAX_ORDER) - 1);
+        let frame_count = end - base;
+
+        // Every order gets its own word aligned part of a single bitmap
+        let mut offsets = [0; ORDER_CNT];
+        let mut bits = 0;
+
+        for order in 0..ORDER_CNT {
+            offsets[order] = bits;
+            bits += align_up(blocks(frame_count, order));
+        }
+
+        let bitmap_frame = bootstrap.allocate_contiguous(Bitmap::size_for(bits))
+                                    .expect("Out of memory");
+
+        let shared_frame = bootstrap.allocate_contiguous(frame_count * size_of::<u32>())
+                                    .expect("Out of memory");
+
+        // Both are reached through the boot physmap
+        assert!(bitmap_frame.address() + Bitmap::size_for(bits) <= BOOT_PHYSMAP_END &&
+                shared_frame.address() + frame_count * size_of::<u32>() <= BOOT_PHYSMAP_END,
+                "Frame allocator metadata outside of the boot physmap");
+
+        let shared = unsafe {
+            slice::from_raw_parts_mut(phys_to_virt(shared_frame.address()) as *mut u32,
+                                      frame_count)
+        };
+
+        for count in shared.iter_mut() {
+            *count = 0;
+        }
+
+        BuddyFrameAllocator {
+            base: base,
+            start: start,
+            end: end,
+            frame_count: frame_count,
+            free: unsafe { Bitmap::new(phys_to_virt(bitmap_frame.address()), bits) },
+            offsets: offsets,
+            free_blocks: [0; ORDER_CNT],
+            shared: shared,
+        }
+    }
+
+    // Frees the part of memory areas managed by this allocator, leaving out
+    // reserved frame ranges
+    pub fn add_areas(&mut self, memory_areas: MemoryAreaIter, reserved: &[(usize, usize)]) {
+        for area in memory_areas {
+            let start = Frame::new(area.base_addr as usize + PAGE_SIZE - 1);
+            let end = Frame::new((area.base_addr + area.length) as usize);
+
+            let start = max(start.number, self.start);
+            let end = min(end.number, self.end);
+
+            self.add_range(start, end, reserved);
+        }
+    }
+
+    pub fn contains(&self, frame: &Frame) -> bool {
+        frame.number >= self.start && frame.number < self.end
+    }
+
+    fn add_range(&mut self, start: usize, end: usize, reserved: &[(usize, usize)]) {
+        if start >= end {
+            return;
+        }
+
+        if let Some((&(res_start, res_end), rest)) = reserved.split_first() {
+            if end <= res_start || start >= res_end {
+                self.add_range(start, end, rest);
+            } else {
+                self.add_range(start, res_start, rest);
+                self.add_range(res_end, end, rest);
+            }
+
+            return;
+        }
+
+        let mut number = start - self.base;
+        let end = end - self.base;
+
+        while number < end {
+            let mut order = min(number.trailing_zeros() as usize, MAX_ORDER);
+
+            while number + (1 << order) > end {
+                order -= 1;
+            }
+
+            self.add_block(number, order);
+
+            number += 1 << order;
+        }
+    }
+
+    // Areas reported by the boot loader may overlap and reclaimed ones may cover
+    // memory that is already free, only the parts which are not get freed
+    fn add_block(&mut self, number: usize, order: usize) {
+        if self.is_free_from(number, order) {
+            return;
+        }
+
+        if order > 0 && self.has_free_below(number, order) {
+            self.add_block(number, order - 1);
+            self.add_block(number + (1 << (order - 1)), order - 1);
+            return;
+        }
+
+        self.free_block(number >> order, order);
+    }
+
+    // Whether any smaller block inside the block is free
+    fn has_free_below(&self, number: usize, order: usize) -> bool {
+        (0..order).any(|o| {
+            let start = self.offsets[o] + (number >> o);
+
+            self.free.find_set(start, start + (1 << (order - o))).is_some()
+        })
+    }
+
+    fn is_free(&self, order: usize, idx: usize) -> bool {
+        self.free.test(self.offsets[order] + idx)
+    }
+
+    // Freed blocks get merged with their buddies, so a frame which is already free
+    // may be covered by a block of any order above its own
+    fn is_free_from(&self, number: usize, order: usize) -> bool {
+        (order..ORDER_CNT).any(|o| self.is_free(o, number >> o))
+    }
+
+    fn set_free(&mut self, order: usize, idx: usize, free: bool) {
+        let bit = self.offsets[order] + idx;
+
+        self.free.set(bit, free);
+
+        if free {
+            self.free_blocks[order] += 1;
+        } else {
+            self.free_blocks[order] -= 1;
+        }
+    }
+
+    // Looks for a free block among the first `limit` blocks of the order
+    fn find_free(&self, order: usize, limit: usize) -> Option<usize> {
+        if self.free_blocks[order] == 0 {
+            return None;
+        }
+
+        let start = self.offsets[order];
+
+        self.free
+            .find_set(start, start + min(blocks(self.frame_count, order), limit))
+            .map(|bit| bit - start)
+    }
+
+    fn free_block(&mut self, mut idx: usize, mut order: usize) {
+        while order < MAX_ORDER {
+            let buddy = idx ^ 1;
+
+            if buddy >= blocks(self.frame_count, order) || !self.is_free(order, buddy) {
+                break;
+            }
+
+            self.set_free(order, buddy, false);
+
+            idx >>= 1;
+            order += 1;
+        }
+
+        self.set_free(order, idx, true);
+    }
+
+    pub fn allocate_order(&mut self, order: usize) -> Option<Frame> {
+        let end = self.end;
+
+        self.allocate_order_below(order, end)
+    }
+
+    // Allocates block ending at or below the frame number `limit`
+    pub fn allocate_order_below(&mut self, order: usize, limit: usize) -> Option<Frame> {
+        assert!(order <= MAX_ORDER, "Invalid allocation order {}", order);
+
+        if limit <= self.base {
+            return None;
+        }
+
+        let limit = limit - self.base;
+
+        for current in order..ORDER_CNT {
+            if let Some(mut idx) = self.find_free(current, limit >> current) {
+                self.set_free(current, idx, false);
+
+                // Split the block, releasing upper halves until the requested order
+                for split in (order..current).rev() {
+                    idx <<= 1;
+                    self.set_free(split, idx + 1, true);
+                }
+
+                return Some(Frame { number: self.base + (idx << order) });
+            }
+        }
+
+        None
+    }
+
+    pub fn deallocate_order(&mut self, frame: Frame, order: usize) {
+        assert!(order <= MAX_ORDER, "Invalid deallocation order {}", order);
+        assert!(frame.number % (1 << order) == 0,
+                "Frame 0x{:x} not aligned to order {}",
+                frame.address(),
+                order);
+        assert!(self.contains(&frame),
+                "Deallocating frame 0x{:x} outside of the allocator's memory",
+                frame.address());
+
+        let number = frame.number - self.base;
+
+        assert!(!self.is_free_from(number, order),
+                "Double free of frame 0x{:x}",
+                frame.address());
+
+        self.free_block(number >> order, order);
+    }
+
+    // Adds a reference to an allocated frame
+    pub fn share(&mut self, frame: &Frame) {
+        let count = &mut self.shared[frame.number - self.base];
+
+        assert!(*count < u32::max_value(),
+                "Too many references to frame 0x{:x}",
+                frame.address());
+
+        *count += 1;
+    }
+
+    pub fn ref_count(&self, frame: &Frame) -> usize {
+        self.shared[frame.number - self.base] as usize + 1
+    }
+
+    pub fn free_frames(&self) -> usize {
+        (0..ORDER_CNT).map(|order| self.free_blocks[order] << order).sum()
+    }
+}
+
+impl FrameAllocator for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<Frame> {
+        self.allocate_order(0)
+    }
+
+    // Drops one reference, the frame is freed with the last one
+    fn deallocate_frame(&mut self, frame: Frame) {
+        let idx = frame.number - self.base;
+
+        if self.shared[idx] > 0 {
+            self.shared[idx] -= 1;
+        } else {
+            self.deallocate_order(frame, 0)
+        }
+    }
+}
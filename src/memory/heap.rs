@@ -0,0 +1,68 @@
+use core::cmp::max;
+
+use alloc;
+use heap_allocator;
+use spin::Mutex;
+
+use arch::mm;
+use memory;
+use memory::PAGE_SIZE;
+use memory::stats;
+use memory::stats::Usage;
+
+pub const HEAP_START: usize = 0xffff_c000_0000_0000;
+pub const HEAP_MAX_SIZE: usize = 256 * 1024 * 1024;
+
+// Minimal number of pages mapped each time the heap runs out of space
+const HEAP_GROW_PAGES: usize = 16;
+
+// End of the mapped part of the heap
+static HEAP_END: Mutex<usize> = Mutex::new(HEAP_START);
+
+// Called by the allocator with its lock held
+fn grow(min_size: usize) -> Option<(usize, usize)> {
+    let pages = max((min_size + PAGE_SIZE - 1) / PAGE_SIZE, HEAP_GROW_PAGES);
+    let size = pages * PAGE_SIZE;
+
+    let mut end = HEAP_END.lock();
+    let start = *end;
+
+    if start + size > HEAP_START + HEAP_MAX_SIZE {
+        return None;
+    }
+
+    for page in 0..pages {
+        match memory::allocate() {
+            Some(frame) => mm::map_to_data(start + page * PAGE_SIZE, frame.address()),
+            None => {
+                for mapped in 0..page {
+                    mm::unmap_and_free(start + mapped * PAGE_SIZE);
+                }
+
+                return None;
+            }
+        }
+    }
+
+    stats::add(Usage::Heap, pages);
+
+    *end += size;
+
+    Some((start, size))
+}
+
+fn oom() -> ! {
+    let (size, align) = heap_allocator::last_failure();
+
+    println!("\n\nOUT OF MEMORY: allocation of {} bytes aligned to {} failed",
+             size,
+             align);
+
+    loop {}
+}
+
+// Needs memory management, nothing can be allocated before
+pub fn init() {
+    heap_allocator::init(grow);
+    alloc::oom::set_oom_handler(oom);
+}
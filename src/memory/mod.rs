@@ -1,13 +1,81 @@
 pub use self::area_frame_allocator::AreaFrameAllocator;
+pub use self::buddy_frame_allocator::{BuddyFrameAllocator, MAX_ORDER};
+pub use self::stats::MemoryStats;
+
+pub mod heap;
+pub mod slab;
+pub mod stack;
+pub mod stats;
+pub mod vmalloc;
 
 mod area_frame_allocator;
+mod bitmap;
+mod buddy_frame_allocator;
+
+use core::cmp::min;
 
 use spin::Mutex;
-use multiboot2::{MemoryAreaIter};
+use cmdline;
+use multiboot2::{MemoryArea, MemoryAreaIter, MemoryAreaType, MemoryMapTag, ModuleIter};
 
 pub const PAGE_SIZE: usize = 4096;
 
-static ALLOCATOR: Mutex<Option<AreaFrameAllocator>> = Mutex::new(None);
+// Boot modules whose frames can be kept out of the allocator, any further ones
+// are ignored and their memory is handed out
+pub const MAX_MODULES: usize = 16;
+
+// Bootstrap allocator, kernel, boot information, symbols, memory past the mem=
+// limit and the modules
+const RESERVED_SLOTS: usize = 5 + MAX_MODULES;
+
+// Frame ranges kept out of the allocator at boot and their count, areas
+// reclaimed later must still skip them
+static RESERVED: Mutex<([(usize, usize); RESERVED_SLOTS], usize)> =
+    Mutex::new(([(0, 0); RESERVED_SLOTS], 0));
+
+// Kept for the checks done after boot, the boot information is never freed
+static MEMORY_MAP: Mutex<Option<&'static MemoryMapTag>> = Mutex::new(None);
+
+// Zones sorted by address, allocations prefer the highest zone allowed so the
+// scarce low memory is left for devices which can't use anything else
+static ZONES: Mutex<[Option<BuddyFrameAllocator>; ZONE_CNT]> = Mutex::new([None, None, None]);
+
+const ZONE_CNT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    // ISA DMA, below 16 MiB
+    Dma = 0,
+    // 32-bit devices, below 4 GiB
+    Dma32 = 1,
+    Normal = 2,
+}
+
+const DMA_END: usize = 0x100_0000 / PAGE_SIZE;
+const DMA32_END: usize = 0x1_0000_0000 / PAGE_SIZE;
+
+impl Zone {
+    // Frame numbers covered by the zone
+    fn frames(&self) -> (usize, usize) {
+        match *self {
+            Zone::Dma => (0, DMA_END),
+            Zone::Dma32 => (DMA_END, DMA32_END),
+            Zone::Normal => (DMA32_END, usize::max_value()),
+        }
+    }
+
+    pub fn containing(frame: &Frame) -> Zone {
+        if frame.number < DMA_END {
+            Zone::Dma
+        } else if frame.number < DMA32_END {
+            Zone::Dma32
+        } else {
+            Zone::Normal
+        }
+    }
+}
+
+const ZONE_LIST: [Zone; ZONE_CNT] = [Zone::Dma, Zone::Dma32, Zone::Normal];
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Frame {
@@ -24,29 +92,241 @@ impl Frame {
     }
 }
 
+// First frame past the mem= limit from the command line
+fn frame_limit() -> usize {
+    cmdline::options().mem.map_or(usize::max_value(), |mem| Frame::new(mem).number)
+}
+
+// Frames of the area below the limit
+fn area_frames(area: &MemoryArea, limit: usize) -> usize {
+    let start = Frame::new(area.base_addr as usize).number;
+    let end = min(Frame::new((area.base_addr + area.length) as usize).number, limit);
+
+    end.saturating_sub(start)
+}
+
 pub trait FrameAllocator {
     fn allocate_frame(&mut self) -> Option<Frame>;
     fn deallocate_frame(&mut self, frame: Frame);
 }
 
+// Symbols is the physical range of the ELF symbol and string tables which the
+// boot loader loaded next to the kernel, if any
 pub fn init(kernel_start: usize,
             kernel_end: usize,
             multiboot_start: usize,
             multiboot_end: usize,
-            memory_areas: MemoryAreaIter) {
-    *ALLOCATOR.lock() = Some(AreaFrameAllocator::new(kernel_start as usize,
-                                                     kernel_end as usize,
-                                                     multiboot_start as usize,
-                                                     multiboot_end as usize,
-                                                     memory_areas));
+            modules: ModuleIter,
+            symbols: Option<(usize, usize)>,
+            memory_map_tag: &'static MemoryMapTag) {
+    *MEMORY_MAP.lock() = Some(memory_map_tag);
+
+    let memory_areas = memory_map_tag.usable_areas();
+
+    let frames = |start: usize, end: usize| (Frame::new(start).number, Frame::new(end).number + 1);
+
+    // The first slot is filled with the bootstrap allocator's range once it is done
+    let mut reserved = [(0, 0); RESERVED_SLOTS];
+    let mut reserved_cnt = 5;
+
+    reserved[1] = frames(kernel_start, kernel_end);
+    reserved[2] = frames(multiboot_start, multiboot_end);
+    reserved[3] = symbols.map_or((0, 0), |(start, end)| frames(start, end));
+
+    let mut module_frames = 0;
+
+    for module in modules {
+        if reserved_cnt == reserved.len() {
+            println!("memory: ignoring boot modules past the first {}", MAX_MODULES);
+            break;
+        }
+
+        let range = frames(module.start_address(), module.end_address());
+
+        reserved[reserved_cnt] = range;
+        reserved_cnt += 1;
+        module_frames += range.1 - range.0;
+    }
+
+    // Whatever is still needed from boot has to stay below the mem= limit, it is
+    // only reachable through the physmap
+    let boot_end = reserved[1..reserved_cnt].iter().map(|&(_, end)| end).max().unwrap_or(0);
+
+    cmdline::raise_mem(boot_end * PAGE_SIZE);
+
+    reserved[4] = (frame_limit(), usize::max_value());
+
+    // ACPI memory is reclaimed later on so it has to be covered as well, memory
+    // past the mem= limit is never handed to the zones
+    let frame_count = memory_map_tag.all_areas()
+                                    .filter(|area| {
+                                        area.typ() == MemoryAreaType::Available ||
+                                        area.typ() == MemoryAreaType::AcpiReclaimable
+                                    })
+                                    .map(|area| (area.base_addr + area.length) as usize)
+                                    .max()
+                                    .map(|end| min(Frame::new(end).number, frame_limit()))
+                                    .unwrap_or(0);
+
+    let mut zones = ZONES.lock();
+
+    // The zones' metadata is kept out of the scarce ISA DMA memory
+    reserved[0] = (0, DMA_END);
+
+    let bootstrap_end = {
+        let mut bootstrap = AreaFrameAllocator::new(&reserved[..reserved_cnt],
+                                                    memory_areas.clone());
+
+        for zone in ZONE_LIST.iter() {
+            let (start, end) = zone.frames();
+
+            if start < frame_count {
+                zones[*zone as usize] = Some(BuddyFrameAllocator::new(&mut bootstrap,
+                                                                      start,
+                                                                      min(end, frame_count)));
+            }
+        }
+
+        let end = bootstrap.next_free_frame().number;
+        end
+    };
+
+    // Everything between DMA_END and the bootstrap allocator's cursor was either
+    // handed out (including the zones' metadata) or skipped over as reserved
+    reserved[0] = (DMA_END, bootstrap_end);
+
+    *RESERVED.lock() = (reserved, reserved_cnt);
+
+    for zone in zones.iter_mut().filter_map(|z| z.as_mut()) {
+        zone.add_areas(memory_areas.clone(), &reserved[..reserved_cnt]);
+    }
+
+    let total = memory_areas.map(|area| area_frames(area, frame_count)).sum();
+    let (kernel, multiboot, symbols) = (reserved[1], reserved[2], reserved[3]);
+
+    stats::init(total,
+                kernel.1 - kernel.0 + symbols.1 - symbols.0,
+                multiboot.1 - multiboot.0,
+                module_frames);
+}
+
+// Hands areas which are no longer needed after boot, like ACPI reclaimable
+// memory once the tables are parsed, over to the allocator
+pub fn reclaim(areas: MemoryAreaIter) {
+    let (reserved, reserved_cnt) = *RESERVED.lock();
+
+    let mut zones = ZONES.lock();
+
+    for zone in zones.iter_mut().filter_map(|z| z.as_mut()) {
+        zone.add_areas(areas.clone(), &reserved[..reserved_cnt]);
+    }
+
+    let limit = frame_limit();
+
+    stats::add_total(areas.map(|area| area_frames(area, limit)).sum());
+}
+
+// Tells whether part of the physical range is RAM or ACPI memory, all of which
+// is mapped write-back in the physmap
+pub fn is_ram(start: usize, end: usize) -> bool {
+    let memory_map_tag = match *MEMORY_MAP.lock() {
+        Some(tag) => tag,
+        None => return false,
+    };
+
+    memory_map_tag.all_areas()
+                  .filter(|area| {
+                      match area.typ() {
+                          MemoryAreaType::Available |
+                          MemoryAreaType::AcpiReclaimable |
+                          MemoryAreaType::AcpiNvs => true,
+                          _ => false,
+                      }
+                  })
+                  .any(|area| {
+                      (area.base_addr as usize) < end &&
+                      start < (area.base_addr + area.length) as usize
+                  })
+}
+
+pub fn stats() -> MemoryStats {
+    let zones = ZONES.lock();
+
+    let free = zones.iter()
+                    .filter_map(|z| z.as_ref())
+                    .map(|z| z.free_frames())
+                    .sum();
+
+    stats::collect(free)
 }
 
 pub fn allocate() -> Option<Frame> {
-    let mut a = ALLOCATOR.lock();
+    allocate_order_in_zone(0, Zone::Normal)
+}
+
+// Frame from the given zone or, if it is exhausted, from a lower one
+pub fn allocate_in_zone(zone: Zone) -> Option<Frame> {
+    allocate_order_in_zone(0, zone)
+}
+
+pub fn allocate_order(order: usize) -> Option<Frame> {
+    allocate_order_in_zone(order, Zone::Normal)
+}
+
+pub fn allocate_order_in_zone(order: usize, zone: Zone) -> Option<Frame> {
+    let mut zones = ZONES.lock();
+
+    zones.iter_mut()
+         .rev()
+         .skip(ZONE_CNT - 1 - zone as usize)
+         .filter_map(|z| z.as_mut())
+         .filter_map(|z| z.allocate_order(order))
+         .next()
+}
+
+// Allocates block placed entirely below the given physical address
+pub fn allocate_below(limit: usize, order: usize) -> Option<Frame> {
+    let limit = limit / PAGE_SIZE;
+    let mut zones = ZONES.lock();
+
+    zones.iter_mut()
+         .rev()
+         .filter_map(|z| z.as_mut())
+         .filter_map(|z| z.allocate_order_below(order, limit))
+         .next()
+}
+
+pub fn deallocate(frame: Frame) {
+    let mut zones = ZONES.lock();
+
+    if let Some(ref mut al) = zones[Zone::containing(&frame) as usize] {
+        al.deallocate_frame(frame);
+    }
+}
+
+pub fn deallocate_order(frame: Frame, order: usize) {
+    let mut zones = ZONES.lock();
+
+    if let Some(ref mut al) = zones[Zone::containing(&frame) as usize] {
+        al.deallocate_order(frame, order);
+    }
+}
+
+// Takes another reference to the frame, each one is released with deallocate()
+pub fn share(frame: &Frame) {
+    let mut zones = ZONES.lock();
+
+    if let Some(ref mut al) = zones[Zone::containing(frame) as usize] {
+        al.share(frame);
+    }
+}
+
+pub fn ref_count(frame: &Frame) -> usize {
+    let zones = ZONES.lock();
 
-    if let Some(ref mut al) = *a {
-        return al.allocate_frame();
+    if let Some(ref al) = zones[Zone::containing(frame) as usize] {
+        return al.ref_count(frame);
     }
 
-    None
+    0
 }
@@ -0,0 +1,337 @@
+use core::cmp::max;
+use core::mem::size_of;
+use core::ptr;
+
+use spin::Mutex;
+
+use arch::mm::phys_to_virt;
+use memory;
+use memory::{Frame, PAGE_SIZE};
+use memory::stats;
+use memory::stats::Usage;
+
+const MAX_CACHES: usize = 32;
+
+static CACHES: Mutex<[Option<&'static SlabCache>; MAX_CACHES]> = Mutex::new([None; MAX_CACHES]);
+
+// General purpose caches for objects without a cache of their own, sorted by
+// size. Objects are aligned to their size
+static SIZE_CLASSES: [SlabCache; 7] = [SlabCache::new("size-16", 16, 16),
+                                       SlabCache::new("size-32", 32, 32),
+                                       SlabCache::new("size-64", 64, 64),
+                                       SlabCache::new("size-128", 128, 128),
+                                       SlabCache::new("size-256", 256, 256),
+                                       SlabCache::new("size-512", 512, 512),
+                                       SlabCache::new("size-1024", 1024, 1024)];
+
+// Header placed at the beginning of every slab frame
+struct Slab {
+    frame: usize,
+    prev: *mut Slab,
+    next: *mut Slab,
+    free: *mut FreeObject,
+    in_use: usize,
+}
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+struct SlabList {
+    head: *mut Slab,
+    count: usize,
+}
+
+impl SlabList {
+    const fn new() -> SlabList {
+        SlabList {
+            head: ptr::null_mut(),
+            count: 0,
+        }
+    }
+
+    unsafe fn push(&mut self, slab: *mut Slab) {
+        (*slab).prev = ptr::null_mut();
+        (*slab).next = self.head;
+
+        if !self.head.is_null() {
+            (*self.head).prev = slab;
+        }
+
+        self.head = slab;
+        self.count += 1;
+    }
+
+    unsafe fn remove(&mut self, slab: *mut Slab) {
+        if (*slab).prev.is_null() {
+            self.head = (*slab).next;
+        } else {
+            (*(*slab).prev).next = (*slab).next;
+        }
+
+        if !(*slab).next.is_null() {
+            (*(*slab).next).prev = (*slab).prev;
+        }
+
+        self.count -= 1;
+    }
+}
+
+struct Slabs {
+    registered: bool,
+    empty: SlabList,
+    partial: SlabList,
+    full: SlabList,
+    allocations: usize,
+    frees: usize,
+}
+
+unsafe impl Send for Slabs {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub slabs: usize,
+    pub free_slabs: usize,
+    pub objects_in_use: usize,
+    pub allocations: usize,
+    pub frees: usize,
+}
+
+pub struct SlabCache {
+    name: &'static str,
+    size: usize,
+    align: usize,
+    slabs: Mutex<Slabs>,
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+impl SlabCache {
+    pub const fn new(name: &'static str, size: usize, align: usize) -> SlabCache {
+        SlabCache {
+            name: name,
+            size: size,
+            align: align,
+            slabs: Mutex::new(Slabs {
+                registered: false,
+                empty: SlabList::new(),
+                partial: SlabList::new(),
+                full: SlabList::new(),
+                allocations: 0,
+                frees: 0,
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn stride(&self) -> usize {
+        align_up(max(self.size, size_of::<FreeObject>()),
+                 max(self.align, size_of::<FreeObject>()))
+    }
+
+    fn first_object(&self) -> usize {
+        align_up(size_of::<Slab>(), max(self.align, size_of::<FreeObject>()))
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        (PAGE_SIZE - self.first_object()) / self.stride()
+    }
+
+    unsafe fn new_slab(&self) -> Option<*mut Slab> {
+        let frame = match memory::allocate() {
+            Some(f) => f,
+            None => return None,
+        };
+
+        stats::add(Usage::Slab, 1);
+
+        let slab = phys_to_virt(frame.address()) as *mut Slab;
+        let start = slab as usize + self.first_object();
+        let stride = self.stride();
+
+        (*slab).frame = frame.number;
+        (*slab).free = ptr::null_mut();
+        (*slab).in_use = 0;
+
+        for i in (0..self.objects_per_slab()).rev() {
+            let object = (start + i * stride) as *mut FreeObject;
+
+            (*object).next = (*slab).free;
+            (*slab).free = object;
+        }
+
+        Some(slab)
+    }
+
+    pub fn alloc(&'static self) -> Option<*mut u8> {
+        assert!(self.align.is_power_of_two(),
+                "Slab cache {}: alignment {} is not a power of two",
+                self.name,
+                self.align);
+        assert!(self.objects_per_slab() > 0,
+                "Slab cache {}: object too big",
+                self.name);
+
+        let mut slabs = self.slabs.lock();
+
+        if !slabs.registered {
+            register(self);
+            slabs.registered = true;
+        }
+
+        unsafe {
+            let slab = if !slabs.partial.head.is_null() {
+                slabs.partial.head
+            } else {
+                let slab = if !slabs.empty.head.is_null() {
+                    let slab = slabs.empty.head;
+                    slabs.empty.remove(slab);
+                    slab
+                } else {
+                    match self.new_slab() {
+                        Some(s) => s,
+                        None => return None,
+                    }
+                };
+
+                slabs.partial.push(slab);
+                slab
+            };
+
+            let object = (*slab).free;
+
+            (*slab).free = (*object).next;
+            (*slab).in_use += 1;
+
+            if (*slab).free.is_null() {
+                slabs.partial.remove(slab);
+                slabs.full.push(slab);
+            }
+
+            slabs.allocations += 1;
+
+            Some(object as *mut u8)
+        }
+    }
+
+    pub fn free(&self, ptr: *mut u8) {
+        let mut slabs = self.slabs.lock();
+
+        unsafe {
+            let slab = (ptr as usize & !(PAGE_SIZE - 1)) as *mut Slab;
+            let object = ptr as *mut FreeObject;
+
+            if (*slab).free.is_null() {
+                slabs.full.remove(slab);
+                slabs.partial.push(slab);
+            }
+
+            (*object).next = (*slab).free;
+            (*slab).free = object;
+            (*slab).in_use -= 1;
+
+            if (*slab).in_use == 0 {
+                slabs.partial.remove(slab);
+                slabs.empty.push(slab);
+            }
+        }
+
+        slabs.frees += 1;
+    }
+
+    // Returns all completely free slabs to the frame allocator
+    pub fn shrink(&self) -> usize {
+        let mut slabs = self.slabs.lock();
+        let mut released = 0;
+
+        unsafe {
+            while !slabs.empty.head.is_null() {
+                let slab = slabs.empty.head;
+
+                slabs.empty.remove(slab);
+                memory::deallocate(Frame { number: (*slab).frame });
+                stats::sub(Usage::Slab, 1);
+
+                released += 1;
+            }
+        }
+
+        released
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let slabs = self.slabs.lock();
+
+        CacheStats {
+            name: self.name,
+            object_size: self.size,
+            objects_per_slab: self.objects_per_slab(),
+            slabs: slabs.empty.count + slabs.partial.count + slabs.full.count,
+            free_slabs: slabs.empty.count,
+            objects_in_use: slabs.allocations - slabs.frees,
+            allocations: slabs.allocations,
+            frees: slabs.frees,
+        }
+    }
+}
+
+fn register(cache: &'static SlabCache) {
+    let mut caches = CACHES.lock();
+
+    for slot in caches.iter_mut() {
+        if slot.is_none() {
+            *slot = Some(cache);
+            return;
+        }
+    }
+
+    println!("Slab cache {} not registered: too many caches", cache.name);
+}
+
+// Smallest general purpose cache which fits objects of the given size and alignment
+pub fn cache_for(size: usize, align: usize) -> Option<&'static SlabCache> {
+    SIZE_CLASSES.iter().find(|cache| cache.size >= size && cache.align >= align)
+}
+
+pub fn alloc(size: usize, align: usize) -> Option<*mut u8> {
+    cache_for(size, align).and_then(|cache| cache.alloc())
+}
+
+// Size and alignment have to match the ones passed to alloc()
+pub fn free(ptr: *mut u8, size: usize, align: usize) {
+    cache_for(size, align).expect("Freeing object of unsupported size").free(ptr);
+}
+
+pub fn shrink_all() -> usize {
+    let caches = *CACHES.lock();
+
+    caches.iter()
+          .filter_map(|c| *c)
+          .map(|c| c.shrink())
+          .sum()
+}
+
+pub fn print_stats() {
+    let caches = *CACHES.lock();
+
+    println!("Slab caches:");
+    for cache in caches.iter().filter_map(|c| *c) {
+        let stats = cache.stats();
+
+        println!("  {}: size {}, slabs {} ({} free), objects {}/{}",
+                 stats.name,
+                 stats.object_size,
+                 stats.slabs,
+                 stats.free_slabs,
+                 stats.objects_in_use,
+                 stats.slabs * stats.objects_per_slab);
+    }
+}
@@ -0,0 +1,95 @@
+use spin::Mutex;
+
+use arch::mm;
+use arch::mm::VirtAddr;
+use memory;
+use memory::PAGE_SIZE;
+use memory::stats;
+use memory::stats::Usage;
+use memory::vmalloc::{RegionKind, VirtualSpace};
+
+pub const STACKS_START: VirtAddr = 0xffff_e000_0000_0000;
+pub const STACKS_SIZE: usize = 64 * 1024 * 1024 * 1024;
+
+// Only stacks live in this range, each one preceded by its guard page
+static STACKS: Mutex<VirtualSpace> = Mutex::new(VirtualSpace::new(STACKS_START, STACKS_SIZE));
+
+pub struct Stack {
+    // Start of the unmapped guard page
+    guard: VirtAddr,
+    pages: usize,
+}
+
+impl Stack {
+    pub fn top(&self) -> VirtAddr {
+        self.bottom() + self.pages * PAGE_SIZE
+    }
+
+    pub fn bottom(&self) -> VirtAddr {
+        self.guard + PAGE_SIZE
+    }
+
+    pub fn guard(&self) -> VirtAddr {
+        self.guard
+    }
+}
+
+// Used by the fault handlers, which may interrupt the holder of the lock
+pub fn is_guard_page(virt: VirtAddr) -> bool {
+    STACKS.try_lock()
+          .and_then(|stacks| stacks.region_of(virt))
+          .map_or(false, |(guard, _)| virt < guard + PAGE_SIZE)
+}
+
+// Bottom and top of the stack the address belongs to, None as well if the
+// stacks are locked
+pub fn bounds(virt: VirtAddr) -> Option<(VirtAddr, VirtAddr)> {
+    STACKS.try_lock()
+          .and_then(|stacks| stacks.region_of(virt))
+          .map(|(guard, size)| (guard + PAGE_SIZE, guard + size))
+}
+
+pub fn allocate(pages: usize) -> Option<Stack> {
+    assert!(pages > 0, "Allocating empty stack");
+
+    let guard = match STACKS.lock().reserve((pages + 1) * PAGE_SIZE, RegionKind::Stack) {
+        Some(g) => g,
+        None => return None,
+    };
+
+    let stack = Stack {
+        guard: guard,
+        pages: pages,
+    };
+
+    for page in (stack.bottom()..stack.top()).step_by(PAGE_SIZE) {
+        match memory::allocate() {
+            Some(frame) => mm::map_to_data(page, frame.address()),
+            None => {
+                for mapped in (stack.bottom()..page).step_by(PAGE_SIZE) {
+                    mm::unmap_and_free(mapped);
+                }
+
+                STACKS.lock().release(guard, &[RegionKind::Stack]);
+
+                return None;
+            }
+        }
+    }
+
+    stats::add(Usage::Stacks, pages);
+
+    Some(stack)
+}
+
+pub fn deallocate(stack: Stack) {
+    let mut stacks = STACKS.lock();
+
+    for page in (stack.bottom()..stack.top()).step_by(PAGE_SIZE) {
+        mm::unmap_and_free(page);
+    }
+
+    stacks.release(stack.guard, &[RegionKind::Stack]).expect("Deallocating unknown stack");
+
+    stats::sub(Usage::Stacks, stack.pages);
+}
@@ -0,0 +1,98 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use memory::PAGE_SIZE;
+
+const USAGE_CNT: usize = 4;
+
+// What allocated frames are used for, frames handed out to other users are not
+// tracked separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Usage {
+    PageTables = 0,
+    Heap = 1,
+    Slab = 2,
+    Stacks = 3,
+}
+
+static USAGE: [AtomicUsize; USAGE_CNT] = [AtomicUsize::new(0),
+                                          AtomicUsize::new(0),
+                                          AtomicUsize::new(0),
+                                          AtomicUsize::new(0)];
+
+// Set by memory::init, total grows as memory gets reclaimed
+static TOTAL: AtomicUsize = AtomicUsize::new(0);
+static KERNEL: AtomicUsize = AtomicUsize::new(0);
+static MULTIBOOT: AtomicUsize = AtomicUsize::new(0);
+static MODULES: AtomicUsize = AtomicUsize::new(0);
+
+// All values are in frames
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryStats {
+    pub total: usize,
+    pub free: usize,
+    pub kernel: usize,
+    pub multiboot: usize,
+    pub modules: usize,
+    pub page_tables: usize,
+    pub heap: usize,
+    pub slab: usize,
+    pub stacks: usize,
+}
+
+pub fn init(total: usize, kernel: usize, multiboot: usize, modules: usize) {
+    TOTAL.store(total, Ordering::Relaxed);
+    KERNEL.store(kernel, Ordering::Relaxed);
+    MULTIBOOT.store(multiboot, Ordering::Relaxed);
+    MODULES.store(modules, Ordering::Relaxed);
+}
+
+pub fn add_total(frames: usize) {
+    TOTAL.fetch_add(frames, Ordering::Relaxed);
+}
+
+pub fn add(usage: Usage, frames: usize) {
+    USAGE[usage as usize].fetch_add(frames, Ordering::Relaxed);
+}
+
+pub fn sub(usage: Usage, frames: usize) {
+    USAGE[usage as usize].fetch_sub(frames, Ordering::Relaxed);
+}
+
+fn used(usage: Usage) -> usize {
+    USAGE[usage as usize].load(Ordering::Relaxed)
+}
+
+pub fn collect(free: usize) -> MemoryStats {
+    MemoryStats {
+        total: TOTAL.load(Ordering::Relaxed),
+        free: free,
+        kernel: KERNEL.load(Ordering::Relaxed),
+        multiboot: MULTIBOOT.load(Ordering::Relaxed),
+        modules: MODULES.load(Ordering::Relaxed),
+        page_tables: used(Usage::PageTables),
+        heap: used(Usage::Heap),
+        slab: used(Usage::Slab),
+        stacks: used(Usage::Stacks),
+    }
+}
+
+fn kib(frames: usize) -> usize {
+    frames * PAGE_SIZE / 1024
+}
+
+impl MemoryStats {
+    pub fn print(&self) {
+        println!("Memory: {} KiB total, {} KiB free",
+                 kib(self.total),
+                 kib(self.free));
+        println!("  kernel image {} KiB, multiboot {} KiB, modules {} KiB",
+                 kib(self.kernel),
+                 kib(self.multiboot),
+                 kib(self.modules));
+        println!("  page tables {} KiB, heap {} KiB, slab {} KiB, stacks {} KiB",
+                 kib(self.page_tables),
+                 kib(self.heap),
+                 kib(self.slab),
+                 kib(self.stacks));
+    }
+}
@@ -0,0 +1,227 @@
+use core::mem::{align_of, size_of};
+use core::ptr;
+
+use spin::Mutex;
+
+use arch::mm;
+use arch::mm::VirtAddr;
+use memory;
+use memory::PAGE_SIZE;
+use memory::slab;
+
+pub const VMALLOC_START: VirtAddr = 0xffff_d000_0000_0000;
+pub const VMALLOC_SIZE: usize = 1024 * 1024 * 1024 * 1024;
+
+// Unmapped space left after every region so overruns fault instead of hitting
+// the next region
+const GUARD_SIZE: usize = PAGE_SIZE;
+
+static SPACE: Mutex<VirtualSpace> = Mutex::new(VirtualSpace::new(VMALLOC_START, VMALLOC_SIZE));
+
+// What a region is used for, so it is only ever freed by its own counterpart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Vmalloc,
+    // Pages are backed on first access by the page fault handler
+    Lazy,
+    // Device memory, the frames do not belong to the frame allocator
+    Ioremap,
+    Stack,
+}
+
+struct Region {
+    start: VirtAddr,
+    size: usize,
+    kind: RegionKind,
+    next: *mut Region,
+}
+
+// Range of kernel virtual memory. Regions handed out so far are kept in a list
+// sorted by address, the nodes come from the slab caches
+pub struct VirtualSpace {
+    start: VirtAddr,
+    size: usize,
+    regions: *mut Region,
+}
+
+unsafe impl Send for VirtualSpace {}
+
+impl VirtualSpace {
+    pub const fn new(start: VirtAddr, size: usize) -> VirtualSpace {
+        VirtualSpace {
+            start: start,
+            size: size,
+            regions: ptr::null_mut(),
+        }
+    }
+
+    pub fn reserve(&mut self, size: usize, kind: RegionKind) -> Option<VirtAddr> {
+        let node = match slab::alloc(size_of::<Region>(), align_of::<Region>()) {
+            Some(n) => n as *mut Region,
+            None => return None,
+        };
+
+        let mut start = self.start;
+        let mut link: *mut *mut Region = &mut self.regions;
+
+        unsafe {
+            while !(*link).is_null() {
+                let region = *link;
+
+                if start + size + GUARD_SIZE <= (*region).start {
+                    break;
+                }
+
+                start = (*region).start + (*region).size + GUARD_SIZE;
+                link = &mut (*region).next;
+            }
+
+            if start + size > self.start + self.size {
+                slab::free(node as *mut u8, size_of::<Region>(), align_of::<Region>());
+                return None;
+            }
+
+            ptr::write(node,
+                       Region {
+                           start: start,
+                           size: size,
+                           kind: kind,
+                           next: *link,
+                       });
+
+            *link = node;
+        }
+
+        Some(start)
+    }
+
+    // Guard gaps do not belong to any region
+    fn containing(&self, virt: VirtAddr) -> Option<&Region> {
+        let mut region = self.regions;
+
+        unsafe {
+            while !region.is_null() && (*region).start <= virt {
+                if virt < (*region).start + (*region).size {
+                    return Some(&*region);
+                }
+
+                region = (*region).next;
+            }
+        }
+
+        None
+    }
+
+    pub fn kind_of(&self, virt: VirtAddr) -> Option<RegionKind> {
+        self.containing(virt).map(|region| region.kind)
+    }
+
+    // Start and size of the region the address belongs to
+    pub fn region_of(&self, virt: VirtAddr) -> Option<(VirtAddr, usize)> {
+        self.containing(virt).map(|region| (region.start, region.size))
+    }
+
+    // Size of the region of one of the kinds starting at the address
+    fn size_at(&self, virt: VirtAddr, kinds: &[RegionKind]) -> Option<usize> {
+        match self.containing(virt) {
+            Some(region) if region.start == virt && kinds.contains(&region.kind) => {
+                Some(region.size)
+            }
+            _ => None,
+        }
+    }
+
+    // Regions of other kinds than the given ones are left alone
+    pub fn release(&mut self, virt: VirtAddr, kinds: &[RegionKind]) -> Option<usize> {
+        let mut link: *mut *mut Region = &mut self.regions;
+
+        unsafe {
+            while !(*link).is_null() {
+                let region = *link;
+
+                if (*region).start == virt {
+                    if !kinds.contains(&(*region).kind) {
+                        return None;
+                    }
+
+                    let size = (*region).size;
+
+                    *link = (*region).next;
+                    slab::free(region as *mut u8, size_of::<Region>(), align_of::<Region>());
+
+                    return Some(size);
+                }
+
+                link = &mut (*region).next;
+            }
+        }
+
+        None
+    }
+}
+
+fn page_align(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+// Reserves virtual space without mapping anything in it
+pub fn reserve(size: usize, kind: RegionKind) -> Option<VirtAddr> {
+    SPACE.lock().reserve(page_align(size), kind)
+}
+
+pub fn region_size(virt: VirtAddr, kind: RegionKind) -> Option<usize> {
+    SPACE.lock().size_at(virt, &[kind])
+}
+
+// Counterpart of reserve(), the region must already be unmapped
+pub fn release(virt: VirtAddr, kind: RegionKind) {
+    SPACE.lock().release(virt, &[kind]).expect("Releasing unreserved virtual region");
+}
+
+pub fn vmalloc(size: usize) -> Option<VirtAddr> {
+    let size = page_align(size);
+
+    let start = match reserve(size, RegionKind::Vmalloc) {
+        Some(s) => s,
+        None => return None,
+    };
+
+    for offset in (0..size).step_by(PAGE_SIZE) {
+        match memory::allocate() {
+            Some(frame) => mm::map_to_data(start + offset, frame.address()),
+            None => {
+                for mapped in (0..offset).step_by(PAGE_SIZE) {
+                    mm::unmap_and_free(start + mapped);
+                }
+
+                release(start, RegionKind::Vmalloc);
+
+                return None;
+            }
+        }
+    }
+
+    Some(start)
+}
+
+// Only reserves the space, frames are allocated as the pages get touched
+pub fn vmalloc_lazy(size: usize) -> Option<VirtAddr> {
+    reserve(size, RegionKind::Lazy)
+}
+
+pub fn is_lazy(virt: VirtAddr) -> bool {
+    SPACE.lock().kind_of(virt) == Some(RegionKind::Lazy)
+}
+
+// Works for both eager and lazy regions, pages never touched are not mapped
+pub fn vfree(virt: VirtAddr) {
+    // Holding the lock keeps the range from being handed out again before it is unmapped
+    let mut space = SPACE.lock();
+
+    let size = space.release(virt, &[RegionKind::Vmalloc, RegionKind::Lazy])
+                    .expect("vfree of address not returned by vmalloc");
+
+    for offset in (0..size).step_by(PAGE_SIZE) {
+        mm::unmap_and_free(virt + offset);
+    }
+}
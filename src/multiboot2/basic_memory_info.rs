@@ -0,0 +1,8 @@
+#[repr(C)]
+pub struct BasicMemoryInfoTag {
+    typ: u32,
+    size: u32,
+    // Both in KiB, lower memory starts at 0 and upper memory at 1 MiB
+    pub memory_lower: u32,
+    pub memory_upper: u32,
+}
@@ -0,0 +1,10 @@
+#[repr(C)]
+pub struct BootDeviceTag {
+    typ: u32,
+    size: u32,
+    // BIOS drive number, 0x80 is the first hard disk
+    pub bios_device: u32,
+    // 0xffffffff if unused
+    pub partition: u32,
+    pub sub_partition: u32,
+}
@@ -0,0 +1,14 @@
+use super::{StrError, tag_str};
+
+#[repr(C)]
+pub struct CommandLineTag {
+    typ: u32,
+    size: u32,
+    string: u8,
+}
+
+impl CommandLineTag {
+    pub fn command_line(&self) -> Result<&'static str, StrError> {
+        unsafe { tag_str(&self.string, self.size, 8) }
+    }
+}
@@ -13,10 +13,27 @@ impl ElfSectionsTag {
     pub fn sections(&'static self) -> ElfSectionIter {
         ElfSectionIter {
             current_section: &self.first_section,
-            remaining_sections: self.number_of_sections - 1,
+            remaining_sections: self.number_of_sections,
             entry_size: self.entry_size,
         }
     }
+
+    // Sections are numbered like in the ELF file, 0 is the null section
+    pub fn section(&'static self, index: u32) -> Option<&'static ElfSection> {
+        if index >= self.number_of_sections {
+            return None;
+        }
+
+        let addr = &self.first_section as *const _ as usize +
+                   index as usize * self.entry_size as usize;
+
+        Some(unsafe { &*(addr as *const ElfSection) })
+    }
+
+    // Section holding the section names
+    pub fn string_table(&'static self) -> Option<&'static ElfSection> {
+        self.section(self.shndx)
+    }
 }
 
 
@@ -35,7 +52,8 @@ impl Iterator for ElfSectionIter {
             None
         } else {
             let section = self.current_section;
-            let next_section_addr = (self.current_section as *const _ as u32) + self.entry_size;
+            let next_section_addr = self.current_section as *const _ as usize +
+                                    self.entry_size as usize;
 
             self.current_section = unsafe { &*(next_section_addr as *const ElfSection) };
             self.remaining_sections -= 1;
@@ -64,11 +82,52 @@ pub struct ElfSection {
     entry_size: u64,
 }
 
+impl ElfSection {
+    // Offset of the name in the string table section
+    pub fn name_index(&self) -> u32 {
+        self.name
+    }
+
+    pub fn section_type(&self) -> Option<ElfSectionType> {
+        match self.typ {
+            0 => Some(ElfSectionType::Unused),
+            1 => Some(ElfSectionType::ProgramSection),
+            2 => Some(ElfSectionType::LinkerSymbolTable),
+            3 => Some(ElfSectionType::StringTable),
+            4 => Some(ElfSectionType::RelaRelocation),
+            5 => Some(ElfSectionType::SymbolHashTable),
+            6 => Some(ElfSectionType::DynamicLinkingTable),
+            7 => Some(ElfSectionType::Note),
+            8 => Some(ElfSectionType::Uninitialized),
+            9 => Some(ElfSectionType::RelRelocation),
+            10 => Some(ElfSectionType::Reserved),
+            11 => Some(ElfSectionType::DynamicLoaderSymbolTable),
+            _ => None,
+        }
+    }
+
+    pub fn has_flag(&self, flag: ElfSectionFlags) -> bool {
+        self.flags & flag as u64 != 0
+    }
+
+    // Index of the associated section, the string table for symbol tables
+    pub fn link(&self) -> u32 {
+        self.link
+    }
+
+    // Size of the entries for sections holding a table
+    pub fn entry_size(&self) -> u64 {
+        self.entry_size
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum ElfSectionType {
     Unused = 0,
     ProgramSection = 1,
     LinkerSymbolTable = 2,
+    StringTable = 3,
     RelaRelocation = 4,
     SymbolHashTable = 5,
     DynamicLinkingTable = 6,
@@ -79,6 +138,7 @@ pub enum ElfSectionType {
     DynamicLoaderSymbolTable = 11,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum ElfSectionFlags {
     Writable = 0x1,
@@ -0,0 +1,55 @@
+#[repr(C, packed)]
+pub struct FramebufferTag {
+    typ: u32,
+    size: u32,
+    pub addr: u64,
+    pub pitch: u32,
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u8,
+    framebuffer_type: u8,
+    // The specification says u8, GRUB writes u16
+    _reserved: u16,
+    color_info: [u8; 6],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorField {
+    pub position: u8,
+    pub size: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramebufferType {
+    // Palette is not exposed
+    Indexed,
+    Rgb {
+        red: ColorField,
+        green: ColorField,
+        blue: ColorField,
+    },
+    // EGA text mode, addr points to the character buffer
+    Text,
+}
+
+impl FramebufferTag {
+    pub fn framebuffer_type(&self) -> Option<FramebufferType> {
+        let field = |idx: usize| {
+            ColorField {
+                position: self.color_info[idx * 2],
+                size: self.color_info[idx * 2 + 1],
+            }
+        };
+
+        match self.framebuffer_type {
+            0 => Some(FramebufferType::Indexed),
+            1 => Some(FramebufferType::Rgb {
+                red: field(0),
+                green: field(1),
+                blue: field(2),
+            }),
+            2 => Some(FramebufferType::Text),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,7 @@
+#[repr(C)]
+pub struct LoadBaseAddrTag {
+    typ: u32,
+    size: u32,
+    // Physical address the image was loaded at
+    pub load_base_addr: u32,
+}
@@ -8,15 +8,39 @@ pub struct MemoryMapTag {
 }
 
 impl MemoryMapTag {
-    pub fn memory_areas(&self) -> MemoryAreaIter {
+    fn areas(&self, typ: Option<MemoryAreaType>) -> MemoryAreaIter {
         let self_ptr = self as *const MemoryMapTag;
         let start_area = (&self.first_area) as *const MemoryArea;
         MemoryAreaIter {
             current_area: start_area,
-            last_area: ((self_ptr as u32) + self.size - self.entry_size) as *const MemoryArea,
+            last_area: (self_ptr as usize + (self.size - self.entry_size) as usize) as *const _,
             entry_size: self.entry_size,
+            typ: typ,
         }
     }
+
+    pub fn all_areas(&self) -> MemoryAreaIter {
+        self.areas(None)
+    }
+
+    pub fn areas_of(&self, typ: MemoryAreaType) -> MemoryAreaIter {
+        self.areas(Some(typ))
+    }
+
+    pub fn usable_areas(&self) -> MemoryAreaIter {
+        self.areas_of(MemoryAreaType::Available)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAreaType {
+    Available,
+    Reserved,
+    // Holds ACPI tables, usable once they are parsed
+    AcpiReclaimable,
+    // Has to be preserved across sleep states
+    AcpiNvs,
+    BadMemory,
 }
 
 #[repr(C)]
@@ -27,11 +51,26 @@ pub struct MemoryArea {
     _reserved: u32,
 }
 
+impl MemoryArea {
+    // Unknown types are treated as reserved
+    pub fn typ(&self) -> MemoryAreaType {
+        match self.typ {
+            1 => MemoryAreaType::Available,
+            3 => MemoryAreaType::AcpiReclaimable,
+            4 => MemoryAreaType::AcpiNvs,
+            5 => MemoryAreaType::BadMemory,
+            _ => MemoryAreaType::Reserved,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct MemoryAreaIter {
     current_area: *const MemoryArea,
     last_area: *const MemoryArea,
     entry_size: u32,
+    // Only areas of this type are returned if set
+    typ: Option<MemoryAreaType>,
 }
 
 impl Iterator for MemoryAreaIter {
@@ -43,9 +82,10 @@ impl Iterator for MemoryAreaIter {
         } else {
             let area = unsafe { &*self.current_area };
 
-            self.current_area = ((self.current_area as u32) + self.entry_size) as *const MemoryArea;
+            let next_area = self.current_area as usize + self.entry_size as usize;
+            self.current_area = next_area as *const MemoryArea;
 
-            if area.typ == 1 {
+            if self.typ.map_or(true, |typ| area.typ() == typ) {
                 Some(area)
             } else {
                 self.next()
@@ -1,8 +1,26 @@
+use core::slice;
+use core::str;
+use core::str::Utf8Error;
+
 mod memory_map;
 mod elf_sections;
-pub use self::memory_map::{MemoryMapTag, MemoryArea, MemoryAreaIter};
+mod command_line;
+mod boot_loader_name;
+mod module;
+mod basic_memory_info;
+mod boot_device;
+mod load_base_addr;
+mod framebuffer;
+pub use self::memory_map::{MemoryMapTag, MemoryArea, MemoryAreaIter, MemoryAreaType};
 pub use self::elf_sections::{ElfSectionsTag, ElfSection, ElfSectionIter, ElfSectionType,
                              ElfSectionFlags};
+pub use self::command_line::CommandLineTag;
+pub use self::boot_loader_name::BootLoaderNameTag;
+pub use self::module::ModuleTag;
+pub use self::basic_memory_info::BasicMemoryInfoTag;
+pub use self::boot_device::BootDeviceTag;
+pub use self::load_base_addr::LoadBaseAddrTag;
+pub use self::framebuffer::{FramebufferTag, FramebufferType, ColorField};
 
 pub unsafe fn load(address: usize) -> &'static BootInformation {
     let multiboot = &*(address as *const BootInformation);
@@ -24,7 +42,55 @@ struct Tag {
     size: u32,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum StrError {
+    // Tag size is smaller than the fields before the string
+    InvalidSize,
+    Utf8(Utf8Error),
+}
+
+// Strings fill the rest of the tag after the header bytes and are null terminated
+unsafe fn tag_str(start: *const u8, size: u32, header: usize) -> Result<&'static str, StrError> {
+    let len = match (size as usize).checked_sub(header) {
+        Some(len) => len,
+        None => return Err(StrError::InvalidSize),
+    };
+
+    let bytes = slice::from_raw_parts(start, len);
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(len);
+
+    str::from_utf8(&bytes[..len]).map_err(StrError::Utf8)
+}
+
 impl BootInformation {
+    pub fn command_line_tag(&self) -> Option<&'static CommandLineTag> {
+        self.get_tag(1).map(|tag| unsafe { &*(tag as *const Tag as *const CommandLineTag) })
+    }
+
+    pub fn boot_loader_name_tag(&self) -> Option<&'static BootLoaderNameTag> {
+        self.get_tag(2).map(|tag| unsafe { &*(tag as *const Tag as *const BootLoaderNameTag) })
+    }
+
+    pub fn module_tags(&self) -> ModuleIter {
+        ModuleIter { iter: self.tags() }
+    }
+
+    pub fn basic_memory_info_tag(&self) -> Option<&'static BasicMemoryInfoTag> {
+        self.get_tag(4).map(|tag| unsafe { &*(tag as *const Tag as *const BasicMemoryInfoTag) })
+    }
+
+    pub fn boot_device_tag(&self) -> Option<&'static BootDeviceTag> {
+        self.get_tag(5).map(|tag| unsafe { &*(tag as *const Tag as *const BootDeviceTag) })
+    }
+
+    pub fn framebuffer_tag(&self) -> Option<&'static FramebufferTag> {
+        self.get_tag(8).map(|tag| unsafe { &*(tag as *const Tag as *const FramebufferTag) })
+    }
+
+    pub fn load_base_addr_tag(&self) -> Option<&'static LoadBaseAddrTag> {
+        self.get_tag(21).map(|tag| unsafe { &*(tag as *const Tag as *const LoadBaseAddrTag) })
+    }
+
     pub fn memory_map_tag(&self) -> Option<&'static MemoryMapTag> {
         self.get_tag(6).map(|tag| unsafe { &*(tag as *const Tag as *const MemoryMapTag) })
     }
@@ -52,6 +118,22 @@ impl BootInformation {
     }
 }
 
+#[derive(Clone)]
+pub struct ModuleIter {
+    iter: TagIter,
+}
+
+impl Iterator for ModuleIter {
+    type Item = &'static ModuleTag;
+
+    fn next(&mut self) -> Option<&'static ModuleTag> {
+        self.iter
+            .find(|tag| tag.typ == 3)
+            .map(|tag| unsafe { &*(tag as *const Tag as *const ModuleTag) })
+    }
+}
+
+#[derive(Clone)]
 struct TagIter {
     current: *const Tag,
 }
@@ -0,0 +1,25 @@
+use super::{StrError, tag_str};
+
+#[repr(C)]
+pub struct ModuleTag {
+    typ: u32,
+    size: u32,
+    mod_start: u32,
+    mod_end: u32,
+    string: u8,
+}
+
+impl ModuleTag {
+    // Physical address range of the module contents
+    pub fn start_address(&self) -> usize {
+        self.mod_start as usize
+    }
+
+    pub fn end_address(&self) -> usize {
+        self.mod_end as usize
+    }
+
+    pub fn command_line(&self) -> Result<&'static str, StrError> {
+        unsafe { tag_str(&self.string, self.size, 16) }
+    }
+}
@@ -0,0 +1,77 @@
+use core::fmt;
+use spin::Mutex;
+
+use arch::cpuio::Port;
+
+const COM1: u16 = 0x3f8;
+
+// Line status register bit set once the transmit buffer can take a byte
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+// Set by init() if console=serial is given, print! goes there instead of the screen
+pub static CONSOLE: Mutex<Option<SerialPort>> = Mutex::new(None);
+
+pub struct SerialPort {
+    data: Port<u8>,
+    interrupt_enable: Port<u8>,
+    fifo_control: Port<u8>,
+    line_control: Port<u8>,
+    modem_control: Port<u8>,
+    line_status: Port<u8>,
+}
+
+impl SerialPort {
+    const unsafe fn new(base: u16) -> SerialPort {
+        SerialPort {
+            data: Port::new(base),
+            interrupt_enable: Port::new(base + 1),
+            fifo_control: Port::new(base + 2),
+            line_control: Port::new(base + 3),
+            modem_control: Port::new(base + 4),
+            line_status: Port::new(base + 5),
+        }
+    }
+
+    // 38400 baud, 8 data bits, no parity, one stop bit, polled
+    fn init(&mut self) {
+        self.interrupt_enable.write(0x00);
+
+        // Divisor latch access, the data and interrupt enable registers hold
+        // the baud rate divisor while it is set
+        self.line_control.write(0x80);
+        self.data.write(0x03);
+        self.interrupt_enable.write(0x00);
+
+        self.line_control.write(0x03);
+        self.fifo_control.write(0xc7);
+        self.modem_control.write(0x03);
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        while self.line_status.read() & LSR_THR_EMPTY == 0 {}
+
+        self.data.write(byte);
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+}
+
+pub fn init() {
+    let mut port = unsafe { SerialPort::new(COM1) };
+
+    port.init();
+
+    *CONSOLE.lock() = Some(port);
+}
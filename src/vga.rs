@@ -1,7 +1,11 @@
+use core::fmt;
 use core::ptr::Unique;
 use spin::Mutex;
 
 use arch::cpuio::Port;
+use arch::mm::phys_to_virt;
+use framebuffer;
+use serial;
 
 macro_rules! println {
     ($fmt:expr) => (print!(concat!($fmt, "\n")));
@@ -10,8 +14,7 @@ macro_rules! println {
 
 macro_rules! print {
     ($($arg:tt)*) => ({
-        use core::fmt::Write;
-        $crate::vga::WRITER.lock().write_fmt(format_args!($($arg)*)).unwrap();
+        $crate::vga::print(format_args!($($arg)*));
     });
 }
 
@@ -62,7 +65,7 @@ pub static WRITER: Mutex<Writer> = Mutex::new(Writer {
     column: 0,
     row: 0,
     color: ColorCode::new(Color::LightGreen, Color::Black),
-    buffer: unsafe { Unique::new(0xb8000 as *mut _) },
+    buffer: unsafe { Unique::new(phys_to_virt(0xb8000) as *mut _) },
 });
 
 struct Buffer {
@@ -178,6 +181,24 @@ impl ::core::fmt::Write for Writer {
     }
 }
 
+// Output goes to the serial or framebuffer console once one is set up, to the
+// text buffer before that
+pub fn print(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    if let Some(ref mut console) = *serial::CONSOLE.lock() {
+        console.write_fmt(args).unwrap();
+        return;
+    }
+
+    if let Some(ref mut console) = *framebuffer::CONSOLE.lock() {
+        console.write_fmt(args).unwrap();
+        return;
+    }
+
+    WRITER.lock().write_fmt(args).unwrap();
+}
+
 pub fn clear_screen() {
     WRITER.lock().clear();
 }
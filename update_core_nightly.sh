@@ -9,3 +9,4 @@ git submodule update
 popd
 mkdir -p ./build
 rustc --target x86_64-unknown-none-gnu -Z no-landing-pads --out-dir ./build ../rust/src/libcore/lib.rs
+rustc --target x86_64-unknown-none-gnu -Z no-landing-pads -L ./build --out-dir ./build ../rust/src/liballoc/lib.rs